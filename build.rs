@@ -0,0 +1,49 @@
+//! Compiles the 2021 day 24 ALU program into a Rust function, which `y2021::alu` includes.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+const ALU_PROGRAM: &str = "data/2021/day24.txt";
+
+/// Translates each instruction to a Rust statement, dropping the ones that don't change
+/// anything (`add a 0`, `div a 1`) and turning `mul a 0` into a plain assignment.
+fn compile_alu(program: &str) -> String {
+    let mut out = String::new();
+    writeln!(out, "#[allow(unused_assignments, unused_mut, clippy::all)]").unwrap();
+    writeln!(
+        out,
+        "pub fn alu(mut input: impl Iterator<Item = i32>) -> i64 {{"
+    )
+    .unwrap();
+    for reg in ["w", "x", "y", "z"].iter() {
+        writeln!(out, "    let mut {}: i64 = 0;", reg).unwrap();
+    }
+    for (line, instr) in program.lines().enumerate() {
+        let parts: Vec<_> = instr.split_whitespace().collect();
+        let stmt = match parts[..] {
+            [] => continue,
+            ["inp", a] => format!("{} = input.next().unwrap() as i64;", a),
+            ["add", _, "0"] | ["div", _, "1"] => continue,
+            ["mul", a, "0"] => format!("{} = 0;", a),
+            ["add", a, b] => format!("{} += {};", a, b),
+            ["mul", a, b] => format!("{} *= {};", a, b),
+            ["div", a, b] => format!("{} /= {};", a, b),
+            ["mod", a, b] => format!("{} %= {};", a, b),
+            ["eql", a, b] => format!("{0} = ({0} == {1}) as i64;", a, b),
+            _ => panic!("{}:{}: bad instruction '{}'", ALU_PROGRAM, line + 1, instr),
+        };
+        writeln!(out, "    {}", stmt).unwrap();
+    }
+    writeln!(out, "    z\n}}").unwrap();
+    out
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={}", ALU_PROGRAM);
+    let program = fs::read_to_string(ALU_PROGRAM).unwrap();
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("alu.rs");
+    fs::write(out, compile_alu(&program)).unwrap();
+}
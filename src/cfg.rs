@@ -0,0 +1,232 @@
+//! Control flow graphs of the small instruction sets in the puzzles, such as the 2020
+//! handheld console and the 2021 ALU.
+//!
+//! A basic block is a run of instructions that is only entered at its first instruction
+//! and only left after its last. Blocks start at the program entry, at jump targets, after
+//! jumps and at instructions that ask for a block of their own. Loops are found from the
+//! back edges of a depth first search from the entry, and the graph can be exported in
+//! Graphviz DOT format.
+
+use std::collections::BTreeSet;
+use std::fmt::{Display, Write};
+
+/// An instruction that the CFG builder can follow.
+pub trait FlowInstr: Display {
+    /// Addresses that can execute after this instruction if it is at `addr`. The address
+    /// one past the end of the program is its exit.
+    fn successors(&self, addr: usize) -> Vec<isize>;
+
+    /// Checks if the instruction should start a new block even if nothing jumps to it.
+    fn starts_block(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Target {
+    Block(usize),
+    Exit,
+    /// Jump outside the program, to somewhere else than its end.
+    OutOfBounds(isize),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    /// Address of the first instruction.
+    pub start: usize,
+    /// Address after the last instruction.
+    pub end: usize,
+    pub succs: Vec<Target>,
+}
+
+fn node_id(target: &Target) -> String {
+    match *target {
+        Target::Block(n) => format!("b{}", n),
+        Target::Exit => "exit".to_string(),
+        Target::OutOfBounds(ip) => format!("oob{}", ip).replace('-', "_"),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cfg {
+    /// Blocks in address order. The first one is the entry.
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl Cfg {
+    pub fn build<I: FlowInstr>(prog: &[I]) -> Self {
+        let len = prog.len();
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        for (addr, instr) in prog.iter().enumerate() {
+            if instr.starts_block() {
+                leaders.insert(addr);
+            }
+            let succs = instr.successors(addr);
+            if succs != [addr as isize + 1] {
+                leaders.insert(addr + 1);
+                for s in succs {
+                    if s >= 0 && (s as usize) < len {
+                        leaders.insert(s as usize);
+                    }
+                }
+            }
+        }
+        let starts: Vec<_> = leaders.into_iter().filter(|&a| a < len).collect();
+        let block_at = |addr: isize| match addr {
+            a if a as usize == len => Target::Exit,
+            a if a < 0 || a as usize > len => Target::OutOfBounds(a),
+            a => Target::Block(starts.binary_search(&(a as usize)).unwrap()),
+        };
+        let blocks = starts
+            .iter()
+            .enumerate()
+            .map(|(n, &start)| {
+                let end = starts.get(n + 1).copied().unwrap_or(len);
+                let mut succs: Vec<_> = prog[end - 1]
+                    .successors(end - 1)
+                    .into_iter()
+                    .map(block_at)
+                    .collect();
+                succs.sort();
+                succs.dedup();
+                BasicBlock { start, end, succs }
+            })
+            .collect();
+        Self { blocks }
+    }
+
+    /// The block containing the instruction at `addr`.
+    pub fn block_of(&self, addr: usize) -> Option<usize> {
+        let n = self.blocks.partition_point(|b| b.start <= addr);
+        n.checked_sub(1).filter(|&n| addr < self.blocks[n].end)
+    }
+
+    fn succ_blocks(&self, n: usize) -> impl Iterator<Item = usize> + '_ {
+        self.blocks[n].succs.iter().filter_map(|t| match t {
+            Target::Block(b) => Some(*b),
+            _ => None,
+        })
+    }
+
+    /// Blocks that can be reached from the entry.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut seen = vec![false; self.blocks.len()];
+        let mut todo = vec![0];
+        while let Some(n) = todo.pop() {
+            if n < seen.len() && !seen[n] {
+                seen[n] = true;
+                todo.extend(self.succ_blocks(n));
+            }
+        }
+        seen
+    }
+
+    /// Edges `(from, to)` that go back to a block on the current path of a depth first
+    /// search from the entry. Every cycle has at least one.
+    pub fn back_edges(&self) -> Vec<(usize, usize)> {
+        #[derive(Copy, Clone, PartialEq)]
+        enum State {
+            New,
+            OnPath,
+            Done,
+        }
+        let mut state = vec![State::New; self.blocks.len()];
+        let mut back = vec![];
+        if self.blocks.is_empty() {
+            return back;
+        }
+        // Blocks on the path, with their successors that are left to visit.
+        let mut path = vec![(0, self.succ_blocks(0).collect::<Vec<_>>())];
+        state[0] = State::OnPath;
+        while let Some((n, succs)) = path.last_mut() {
+            let n = *n;
+            match succs.pop() {
+                Some(s) => match state[s] {
+                    State::New => {
+                        state[s] = State::OnPath;
+                        path.push((s, self.succ_blocks(s).collect()));
+                    }
+                    State::OnPath => back.push((n, s)),
+                    State::Done => {}
+                },
+                None => {
+                    state[n] = State::Done;
+                    path.pop();
+                }
+            }
+        }
+        back.sort_unstable();
+        back
+    }
+
+    /// The loops of the program, as the loop header and the blocks in the loop, in
+    /// address order. Each back edge gives the reachable blocks that reach its source
+    /// without passing the header.
+    pub fn loops(&self) -> Vec<(usize, Vec<usize>)> {
+        let reachable = self.reachable();
+        let mut preds = vec![vec![]; self.blocks.len()];
+        for n in (0..self.blocks.len()).filter(|&n| reachable[n]) {
+            for s in self.succ_blocks(n) {
+                preds[s].push(n);
+            }
+        }
+        self.back_edges()
+            .into_iter()
+            .map(|(from, header)| {
+                let mut body = BTreeSet::new();
+                body.insert(header);
+                let mut todo = vec![from];
+                while let Some(n) = todo.pop() {
+                    if body.insert(n) {
+                        todo.extend(&preds[n]);
+                    }
+                }
+                (header, body.into_iter().collect())
+            })
+            .collect()
+    }
+
+    /// Graphviz DOT for the graph, with the instructions in each block. Instructions at
+    /// the `highlight` addresses are marked and their blocks filled, back edges are red.
+    pub fn to_dot<I: FlowInstr>(&self, prog: &[I], highlight: &[usize]) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph cfg {{").unwrap();
+        writeln!(out, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+        let back = self.back_edges();
+        let mut exits = BTreeSet::new();
+        for (n, block) in self.blocks.iter().enumerate() {
+            let mut label = String::new();
+            for (addr, instr) in prog[block.start..block.end].iter().enumerate() {
+                let addr = block.start + addr;
+                let mark = if highlight.contains(&addr) { "* " } else { "" };
+                let line = format!("{}{}: {}", mark, addr, instr);
+                label += &line.replace('\\', "\\\\").replace('"', "\\\"");
+                label += "\\l";
+            }
+            let style = match (block.start..block.end).any(|a| highlight.contains(&a)) {
+                true => ", style=filled, fillcolor=\"#ffcccc\"",
+                false => "",
+            };
+            writeln!(out, "    b{} [label=\"{}\"{}];", n, label, style).unwrap();
+            for target in &block.succs {
+                let attrs = match *target {
+                    Target::Block(s) if back.contains(&(n, s)) => " [color=red]",
+                    _ => "",
+                };
+                writeln!(out, "    b{} -> {}{};", n, node_id(target), attrs).unwrap();
+                exits.insert(*target);
+            }
+        }
+        for target in exits {
+            let label = match target {
+                Target::Exit => "shape=doublecircle, label=\"end\"".to_string(),
+                Target::OutOfBounds(ip) => format!("shape=octagon, label=\"ip {}\"", ip),
+                Target::Block(_) => continue,
+            };
+            writeln!(out, "    {} [{}];", node_id(&target), label).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
@@ -2,30 +2,116 @@ use arrayvec::ArrayVec;
 use itertools::Itertools;
 
 use std::cmp::{max, min};
+use std::collections::HashMap;
 use std::fmt::{Debug, Display, Formatter};
 use std::iter::Enumerate;
-use std::ops::{Index, IndexMut};
+use std::ops::{Add, AddAssign, Index, IndexMut, Mul, Neg, Sub, SubAssign};
 use std::slice::ChunksExact;
 use std::str::FromStr;
 
+mod serial;
+mod tracked;
+
+pub use serial::{text_diff, BinaryTile};
+pub use tracked::{Change, TrackedGrid};
+
+/// Integer types that can be used for the components of a `Coord`.
+pub trait CoordNum:
+    Copy
+    + Ord
+    + Debug
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Neg<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn abs(self) -> Self;
+}
+
+macro_rules! coord_num {
+    ($($t:ty),*) => {
+        $(impl CoordNum for $t {
+            const ZERO: Self = 0;
+            const ONE: Self = 1;
+
+            fn abs(self) -> Self {
+                <$t>::abs(self)
+            }
+        })*
+    };
+}
+
+coord_num!(i32, i64);
+
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Coord {
-    pub x: i32,
-    pub y: i32,
+pub struct Coord<N = i32> {
+    pub x: N,
+    pub y: N,
 }
 
-impl Coord {
-    pub fn new(x: i32, y: i32) -> Self {
+/// Coordinate for puzzles where the values don't fit in an `i32`.
+pub type Coord64 = Coord<i64>;
+
+impl<N> Coord<N> {
+    pub fn new(x: N, y: N) -> Self {
         Self { x, y }
     }
-    pub fn offset(&self, x: i32, y: i32) -> Coord {
-        Coord::new(self.x + x, self.y + y)
+}
+
+impl<N: CoordNum> Coord<N> {
+    pub fn offset(&self, x: N, y: N) -> Self {
+        Self::new(self.x + x, self.y + y)
+    }
+
+    pub fn manhattan(&self, other: Self) -> N {
+        (self.x - other.x).abs() + (self.y - other.y).abs()
+    }
+
+    /// The number of king moves between the coordinates.
+    pub fn chebyshev(&self, other: Self) -> N {
+        max((self.x - other.x).abs(), (self.y - other.y).abs())
+    }
+
+    /// Rotates a quarter turn clockwise around the origin, with the y axis pointing down
+    /// as in a `Grid`.
+    pub fn rotate_cw(&self) -> Self {
+        Self::new(-self.y, self.x)
+    }
+
+    /// Rotates a quarter turn counter-clockwise around the origin, with the y axis
+    /// pointing down as in a `Grid`.
+    pub fn rotate_ccw(&self) -> Self {
+        Self::new(self.y, -self.x)
     }
 
+    /// Rotates `quarter_turns` clockwise around the origin. Negative values rotate
+    /// counter-clockwise.
+    pub fn rotate(&self, quarter_turns: i32) -> Self {
+        match quarter_turns.rem_euclid(4) {
+            0 => *self,
+            1 => self.rotate_cw(),
+            2 => -*self,
+            _ => self.rotate_ccw(),
+        }
+    }
+
+    /// Returns an iterator over the coordinates on the line to `end`, with any slope.
+    /// Both end points are included.
+    pub fn bresenham(&self, end: Self) -> Bresenham<N> {
+        Bresenham::new(*self, end)
+    }
+}
+
+impl Coord {
     pub fn line(&self, direction: (i32, i32)) -> LineIter {
         LineIter::new(direction, &self)
     }
 
+    /// Returns an iterator over the coordinates to `end`, inclusive. Only works for
+    /// horizontal, vertical and diagonal lines, see `bresenham()` for other slopes.
     pub fn line_to(&self, end: Coord) -> LineTo {
         let dx = (end.x - self.x).signum();
         let dy = (end.y - self.y).signum();
@@ -37,16 +123,114 @@ impl Coord {
     }
 }
 
-impl FromStr for Coord {
+impl<N: CoordNum> Add for Coord<N> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl<N: CoordNum> AddAssign for Coord<N> {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl<N: CoordNum> Sub for Coord<N> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl<N: CoordNum> SubAssign for Coord<N> {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl<N: CoordNum> Mul<N> for Coord<N> {
+    type Output = Self;
+
+    fn mul(self, rhs: N) -> Self::Output {
+        Self::new(self.x * rhs, self.y * rhs)
+    }
+}
+
+impl<N: CoordNum> Neg for Coord<N> {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self::new(-self.x, -self.y)
+    }
+}
+
+impl<N: Display> Display for Coord<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.x, self.y)
+    }
+}
+
+impl<N: FromStr> FromStr for Coord<N> {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (x, y) = s
-            .split(',')
-            .map(|n| n.parse().unwrap())
-            .collect_tuple()
-            .unwrap();
-        Ok(Self { x, y })
+        let (x, y) = s.split(',').collect_tuple().ok_or(())?;
+        Ok(Self {
+            x: x.parse().map_err(|_| ())?,
+            y: y.parse().map_err(|_| ())?,
+        })
+    }
+}
+
+pub struct Bresenham<N> {
+    curr: Coord<N>,
+    end: Coord<N>,
+    delta: Coord<N>,
+    step: Coord<N>,
+    err: N,
+    done: bool,
+}
+
+impl<N: CoordNum> Bresenham<N> {
+    fn new(start: Coord<N>, end: Coord<N>) -> Self {
+        let sign = |a: N, b: N| if a < b { N::ONE } else { -N::ONE };
+        let delta = Coord::new((end.x - start.x).abs(), -(end.y - start.y).abs());
+        Self {
+            curr: start,
+            end,
+            delta,
+            step: Coord::new(sign(start.x, end.x), sign(start.y, end.y)),
+            err: delta.x + delta.y,
+            done: false,
+        }
+    }
+}
+
+impl<N: CoordNum> Iterator for Bresenham<N> {
+    type Item = Coord<N>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let ret = self.curr;
+        if self.curr == self.end {
+            self.done = true;
+            return Some(ret);
+        }
+        let e2 = self.err + self.err;
+        if e2 >= self.delta.y {
+            self.err = self.err + self.delta.y;
+            self.curr.x = self.curr.x + self.step.x;
+        }
+        if e2 <= self.delta.x {
+            self.err = self.err + self.delta.x;
+            self.curr.y = self.curr.y + self.step.y;
+        }
+        Some(ret)
     }
 }
 
@@ -95,6 +279,18 @@ impl Iterator for LineTo {
     }
 }
 
+/// The eight directions to the neighbouring tiles, as `(dx, dy)` pairs.
+pub const DIRECTIONS: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
 pub struct Grid<T> {
     width: i32,
     height: i32,
@@ -189,6 +385,112 @@ impl<T> Grid<T> {
         GridLine::new(self, start, direction)
     }
 
+    /// Returns a mutable iterator for the tiles along the given line. `start` is not
+    /// included in the set.
+    pub fn line_mut(&mut self, start: Coord, direction: (i32, i32)) -> GridLineMut<'_, T> {
+        GridLineMut::new(self, start, direction)
+    }
+
+    /// Returns row `y` as a slice.
+    pub fn row(&self, y: i32) -> &[T] {
+        assert!(y >= 0 && y < self.height, "Row outside of grid.");
+        let w = self.width as usize;
+        &self.tiles[y as usize * w..(y as usize + 1) * w]
+    }
+
+    /// Returns an iterator over the tiles in column `x`, from top to bottom.
+    pub fn column(&self, x: i32) -> GridLine<'_, T> {
+        assert!(x >= 0 && x < self.width, "Column outside of grid.");
+        self.line(Coord::new(x, -1), (0, 1))
+    }
+
+    /// Returns a mutable iterator over the tiles in column `x`, from top to bottom.
+    pub fn column_mut(&mut self, x: i32) -> GridLineMut<'_, T> {
+        assert!(x >= 0 && x < self.width, "Column outside of grid.");
+        self.line_mut(Coord::new(x, -1), (0, 1))
+    }
+
+    /// Returns an iterator over all the columns in the grid, from left to right.
+    pub fn columns(&self) -> impl Iterator<Item = GridLine<'_, T>> {
+        (0..self.width).map(move |x| self.column(x))
+    }
+
+    /// Returns an iterator over the diagonal going down and to the right through `coord`,
+    /// starting at the edge of the grid.
+    pub fn diagonal(&self, coord: Coord) -> GridLine<'_, T> {
+        let start = self.diagonal_start(coord, (1, 1));
+        self.line(start, (1, 1))
+    }
+
+    /// Returns a mutable iterator over the diagonal going down and to the right through
+    /// `coord`, starting at the edge of the grid.
+    pub fn diagonal_mut(&mut self, coord: Coord) -> GridLineMut<'_, T> {
+        let start = self.diagonal_start(coord, (1, 1));
+        self.line_mut(start, (1, 1))
+    }
+
+    /// Returns an iterator over the diagonal going up and to the right through `coord`,
+    /// starting at the edge of the grid.
+    pub fn anti_diagonal(&self, coord: Coord) -> GridLine<'_, T> {
+        let start = self.diagonal_start(coord, (1, -1));
+        self.line(start, (1, -1))
+    }
+
+    /// Returns a mutable iterator over the diagonal going up and to the right through
+    /// `coord`, starting at the edge of the grid.
+    pub fn anti_diagonal_mut(&mut self, coord: Coord) -> GridLineMut<'_, T> {
+        let start = self.diagonal_start(coord, (1, -1));
+        self.line_mut(start, (1, -1))
+    }
+
+    /// Returns the coordinate just outside the grid from which a line in `direction`
+    /// passes through `coord`.
+    fn diagonal_start(&self, coord: Coord, direction: (i32, i32)) -> Coord {
+        assert!(
+            self.valid_coord(coord),
+            "Diagonal through coord outside of grid."
+        );
+        let mut start = coord;
+        while self.valid_coord(start) {
+            start = start.offset(-direction.0, -direction.1);
+        }
+        start
+    }
+
+    /// Returns the first tile matching `pred` when moving from `start` in `direction`,
+    /// or `None` if the edge of the grid is reached first. `start` is not included.
+    pub fn ray<P>(&self, start: Coord, direction: (i32, i32), mut pred: P) -> Option<(Coord, &T)>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        start
+            .line(direction)
+            .take_while(|&c| self.valid_coord(c))
+            .map(|c| (c, &self[c]))
+            .find(|(_, t)| pred(t))
+    }
+
+    /// Returns the first tile matching `pred` when moving from `start` in `direction`, as a
+    /// mutable reference, or `None` if the edge of the grid is reached first.
+    pub fn ray_mut<P>(&mut self, start: Coord, direction: (i32, i32), pred: P) -> Option<&mut T>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        let (c, _) = self.ray(start, direction, pred)?;
+        Some(&mut self[c])
+    }
+
+    /// Returns an iterator giving the first tile matching `pred` in each of the eight
+    /// directions from `start`. Directions where no tile matches are skipped.
+    pub fn rays<P>(&self, start: Coord, mut pred: P) -> impl Iterator<Item = (Coord, &T)>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        DIRECTIONS
+            .iter()
+            .filter_map(move |&d| self.ray(start, d, &mut pred))
+    }
+
     /// Returns an iterator for all the coordinates in the grid
     pub fn coords(&self) -> AllCoords {
         AllCoords {
@@ -219,6 +521,123 @@ impl<T> Grid<T> {
     pub fn valid_coord(&self, c: Coord) -> bool {
         !(c.x < 0 || c.x >= self.width || c.y < 0 || c.y >= self.height)
     }
+
+    /// Returns the top left and bottom right corners of the smallest rectangle containing
+    /// all the tiles matching `pred`, or `None` if there are no matches.
+    pub fn bounding_box<P: FnMut(&T) -> bool>(&self, mut pred: P) -> Option<(Coord, Coord)> {
+        let (mut min, mut max) = (
+            Coord::new(i32::MAX, i32::MAX),
+            Coord::new(i32::MIN, i32::MIN),
+        );
+        for (c, _) in self.iter_tiles().filter(|(_, t)| pred(t)) {
+            min = Coord::new(min.x.min(c.x), min.y.min(c.y));
+            max = Coord::new(max.x.max(c.x), max.y.max(c.y));
+        }
+        if min.x > max.x {
+            return None;
+        }
+        Some((min, max))
+    }
+}
+
+impl<T: Clone> Grid<T> {
+    /// Returns a copy of the grid with `border` tiles of `fill` added on all four sides.
+    pub fn pad(&self, border: i32, fill: T) -> Self {
+        assert!(border >= 0, "Negative padding.");
+        let width = self.width + 2 * border;
+        let height = self.height + 2 * border;
+        let mut tiles = Vec::with_capacity((width * height) as usize);
+        tiles.resize((width * border + border) as usize, fill.clone());
+        for row in self.row_slices() {
+            tiles.extend_from_slice(row);
+            tiles.resize(tiles.len() + 2 * border as usize, fill.clone());
+        }
+        tiles.resize((width * height) as usize, fill);
+        Grid::new(tiles, width, height)
+    }
+
+    /// Returns the `width` x `height` sub grid with its top left corner at `top_left`.
+    pub fn crop(&self, top_left: Coord, width: i32, height: i32) -> Self {
+        assert!(width > 0 && height > 0, "Empty crop.");
+        assert!(
+            self.valid_coord(top_left) && self.valid_coord(top_left.offset(width - 1, height - 1)),
+            "Crop outside of grid."
+        );
+        let mut tiles = Vec::with_capacity((width * height) as usize);
+        for row in self
+            .row_slices()
+            .skip(top_left.y as usize)
+            .take(height as usize)
+        {
+            tiles.extend_from_slice(&row[top_left.x as usize..(top_left.x + width) as usize]);
+        }
+        Grid::new(tiles, width, height)
+    }
+
+    /// Crops the grid to the smallest rectangle containing all the tiles matching `pred`.
+    /// Returns `None` if no tile matches.
+    pub fn crop_to<P: FnMut(&T) -> bool>(&self, pred: P) -> Option<Self> {
+        let (min, max) = self.bounding_box(pred)?;
+        Some(self.crop(min, max.x - min.x + 1, max.y - min.y + 1))
+    }
+
+    /// Builds a grid of `nx` x `ny` copies of this grid. Each tile is mapped through
+    /// `transform`, which gets the position of the copy (in copies, not tiles) and the
+    /// original tile value.
+    pub fn tile<F>(&self, nx: i32, ny: i32, mut transform: F) -> Self
+    where
+        F: FnMut(Coord, &T) -> T,
+    {
+        assert!(nx > 0 && ny > 0, "Tile count must be positive.");
+        let mut tiles = Vec::with_capacity((self.width * self.height * nx * ny) as usize);
+        for copy_y in 0..ny {
+            for row in self.row_slices() {
+                for copy_x in 0..nx {
+                    let copy = Coord::new(copy_x, copy_y);
+                    tiles.extend(row.iter().map(|t| transform(copy, t)));
+                }
+            }
+        }
+        Grid::new(tiles, self.width * nx, self.height * ny)
+    }
+
+    /// Builds a grid covering all the coordinates in `tiles`, with `fill` for the missing
+    /// ones. Returns the grid and the coordinate of its top left corner, or `None` if
+    /// `tiles` is empty.
+    pub fn from_sparse(tiles: &HashMap<Coord, T>, fill: T) -> Option<(Self, Coord)> {
+        let (min_x, max_x) = tiles.keys().map(|c| c.x).minmax().into_option()?;
+        let (min_y, max_y) = tiles.keys().map(|c| c.y).minmax().into_option()?;
+        let top_left = Coord::new(min_x, min_y);
+        let mut grid = Grid::new(
+            vec![fill; ((max_x - min_x + 1) * (max_y - min_y + 1)) as usize],
+            max_x - min_x + 1,
+            max_y - min_y + 1,
+        );
+        for (&c, t) in tiles {
+            grid[c - top_left] = t.clone();
+        }
+        Some((grid, top_left))
+    }
+
+    /// Joins the two grids side by side, with `other` to the right of `self`.
+    pub fn hconcat(&self, other: &Self) -> Self {
+        assert_eq!(self.height, other.height, "Grid heights differ.");
+        let mut tiles = Vec::with_capacity(self.tiles.len() + other.tiles.len());
+        for (left, right) in self.row_slices().zip(other.row_slices()) {
+            tiles.extend_from_slice(left);
+            tiles.extend_from_slice(right);
+        }
+        Grid::new(tiles, self.width + other.width, self.height)
+    }
+
+    /// Joins the two grids vertically, with `other` below `self`.
+    pub fn vconcat(&self, other: &Self) -> Self {
+        assert_eq!(self.width, other.width, "Grid widths differ.");
+        let mut tiles = Vec::with_capacity(self.tiles.len() + other.tiles.len());
+        tiles.extend_from_slice(&self.tiles);
+        tiles.extend_from_slice(&other.tiles);
+        Grid::new(tiles, self.width, self.height + other.height)
+    }
 }
 
 impl<T: Clone> Clone for Grid<T> {
@@ -273,7 +692,7 @@ impl<T: Display> Display for Grid<T> {
 
 impl<T: PartialEq> PartialEq for Grid<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.tiles == other.tiles
+        self.width == other.width && self.tiles == other.tiles
     }
 }
 
@@ -453,6 +872,7 @@ impl<'a, T> Iterator for UpDownLeftRight<'a, T> {
     }
 }
 
+/// Iterator over the tiles along a line through the grid, see `Grid::line()`.
 pub struct GridLine<'a, T> {
     grid: &'a Grid<T>,
     coord: Coord,
@@ -485,3 +905,122 @@ impl<'a, T> Iterator for GridLine<'a, T> {
         Some(&self.grid[self.coord])
     }
 }
+
+/// Mutable iterator over the tiles along a line through the grid, see `Grid::line_mut()`.
+pub struct GridLineMut<'a, T> {
+    /// The part of the tiles that the line hasn't passed yet.
+    tiles: &'a mut [T],
+    /// Grid index of `tiles[0]`.
+    offset: usize,
+    forward: bool,
+    width: i32,
+    height: i32,
+    coord: Coord,
+    direction: (i32, i32),
+}
+
+impl<'a, T> GridLineMut<'a, T> {
+    fn new(grid: &'a mut Grid<T>, start: Coord, direction: (i32, i32)) -> Self {
+        assert_ne!(direction, (0, 0), "Line without direction.");
+        Self {
+            forward: direction.1 * grid.width + direction.0 > 0,
+            tiles: grid.tiles.as_mut_slice(),
+            offset: 0,
+            width: grid.width,
+            height: grid.height,
+            coord: start,
+            direction,
+        }
+    }
+}
+
+impl<'a, T> Iterator for GridLineMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.coord.x += self.direction.0;
+        self.coord.y += self.direction.1;
+        if self.coord.x < 0
+            || self.coord.x >= self.width
+            || self.coord.y < 0
+            || self.coord.y >= self.height
+        {
+            return None;
+        }
+        // The tile indices along a line are strictly monotonic, so the remaining tiles can
+        // be split off on each step without handing out overlapping borrows.
+        let idx = (self.coord.y * self.width + self.coord.x) as usize;
+        let tiles = std::mem::take(&mut self.tiles);
+        let (head, tail) = tiles.split_at_mut(idx - self.offset);
+        let (tile, tail) = tail.split_first_mut()?;
+        if self.forward {
+            self.tiles = tail;
+            self.offset = idx + 1;
+        } else {
+            self.tiles = head;
+        }
+        Some(tile)
+    }
+}
+
+#[test]
+fn coord_ops() {
+    let a = Coord::new(1, 2);
+    let b = Coord::new(4, -2);
+    assert_eq!(a + b, Coord::new(5, 0));
+    assert_eq!(a - b, Coord::new(-3, 4));
+    assert_eq!(-a * 3, Coord::new(-3, -6));
+    assert_eq!(a.manhattan(b), 7);
+    assert_eq!(a.chebyshev(b), 4);
+    assert_eq!(Coord::new(1, 0).rotate_cw(), Coord::new(0, 1));
+    assert_eq!(a.rotate(-1), a.rotate_ccw());
+    assert_eq!(a.rotate(2).rotate(6), a);
+    assert_eq!(
+        Coord::new(0, 0).bresenham(Coord::new(5, -2)).collect_vec(),
+        [(0, 0), (1, 0), (2, -1), (3, -1), (4, -2), (5, -2)]
+            .iter()
+            .map(|&(x, y)| Coord::new(x, y))
+            .collect_vec()
+    );
+    assert_eq!(a.bresenham(a).count(), 1);
+    let far = Coord64::new(3_000_000_000, 0);
+    assert_eq!(far.manhattan(Coord64::new(0, 1)), 3_000_000_001);
+}
+
+#[test]
+fn line_iterators() {
+    let mut g = Grid::from_lines(["abc", "def", "ghi"], |c| c);
+    assert_eq!(g.row(1), ['d', 'e', 'f']);
+    assert_eq!(g.column(1).collect::<String>(), "beh");
+    assert_eq!(
+        g.columns().map(|c| c.collect::<String>()).collect_vec(),
+        ["adg", "beh", "cfi"]
+    );
+    assert_eq!(g.diagonal(Coord::new(1, 0)).collect::<String>(), "bf");
+    assert_eq!(g.anti_diagonal(Coord::new(1, 1)).collect::<String>(), "gec");
+    assert_eq!(
+        g.ray(Coord::new(0, 0), (1, 1), |&c| c > 'e'),
+        Some((Coord::new(2, 2), &'i'))
+    );
+    assert_eq!(g.rays(Coord::new(1, 1), |&c| c < 'c').count(), 2);
+
+    g.anti_diagonal_mut(Coord::new(0, 2)).for_each(|t| *t = '/');
+    g.column_mut(0).for_each(|t| *t = '|');
+    g.line_mut(Coord::new(3, 0), (-1, 0)).for_each(|t| *t = '-');
+    *g.ray_mut(Coord::new(2, 2), (0, -1), |&c| c == 'f').unwrap() = '!';
+    assert_eq!(g.to_string(), "---\n|/!\n|hi\n");
+}
+
+#[test]
+fn resize_ops() {
+    let g = Grid::from_lines(["ab", "cd"], |c| c);
+    let padded = g.pad(1, '.');
+    assert_eq!(padded.to_string(), "....\n.ab.\n.cd.\n....\n");
+    assert_eq!(padded.crop_to(|&c| c != '.').unwrap(), g);
+    assert_eq!(padded.crop(Coord::new(1, 2), 2, 1).to_string(), "cd\n");
+    assert!(padded.crop_to(|&c| c == 'x').is_none());
+    let tiled = g.tile(2, 1, |copy, &c| if copy.x == 0 { c } else { '#' });
+    assert_eq!(tiled.to_string(), "ab##\ncd##\n");
+    assert_eq!(g.hconcat(&g).to_string(), "abab\ncdcd\n");
+    assert_eq!(g.vconcat(&g).to_string(), "ab\ncd\nab\ncd\n");
+}
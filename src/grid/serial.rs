@@ -0,0 +1,306 @@
+//! Stable text and binary formats for `Grid` and `Coord`, for storing snapshots of
+//! intermediate puzzle states as test fixtures.
+//!
+//! Text format: a `<width>x<height>` header line followed by one line per row, with the
+//! tiles written with `Display` and separated by single spaces. Spaces, line breaks and
+//! backslashes in a tile are escaped as `\s`, `\n`, `\r` and `\\`, and an empty tile is
+//! written as `\e`.
+//!
+//! Binary format: the magic bytes `GRID`, width and height as little endian `u32`, and
+//! then the tiles in row order encoded with `BinaryTile`.
+
+use super::{Coord, Grid};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use itertools::Itertools;
+
+use std::convert::TryInto;
+use std::fmt::Display;
+use std::str::FromStr;
+
+const MAGIC: &[u8; 4] = b"GRID";
+
+/// Fixed size little endian encoding of a value, used by the binary grid format.
+pub trait BinaryTile: Sized {
+    /// Number of bytes in the encoding.
+    const SIZE: usize;
+
+    fn write_bytes(&self, out: &mut Vec<u8>);
+
+    /// Decodes a value from the start of `input`, and advances `input` past it.
+    fn read_bytes(input: &mut &[u8]) -> Result<Self>;
+}
+
+fn take_bytes<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    ensure!(input.len() >= n, "Unexpected end of grid data");
+    let (bytes, rest) = input.split_at(n);
+    *input = rest;
+    Ok(bytes)
+}
+
+macro_rules! binary_int {
+    ($($t:ty),*) => {
+        $(impl BinaryTile for $t {
+            const SIZE: usize = std::mem::size_of::<$t>();
+
+            fn write_bytes(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn read_bytes(input: &mut &[u8]) -> Result<Self> {
+                let bytes = take_bytes(input, Self::SIZE)?;
+                Ok(<$t>::from_le_bytes(bytes.try_into().unwrap()))
+            }
+        })*
+    };
+}
+
+binary_int!(u8, i8, u16, i16, u32, i32, u64, i64);
+
+impl BinaryTile for bool {
+    const SIZE: usize = 1;
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn read_bytes(input: &mut &[u8]) -> Result<Self> {
+        match u8::read_bytes(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => bail!("Invalid bool {}", b),
+        }
+    }
+}
+
+impl BinaryTile for char {
+    const SIZE: usize = 4;
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        (*self as u32).write_bytes(out);
+    }
+
+    fn read_bytes(input: &mut &[u8]) -> Result<Self> {
+        let c = u32::read_bytes(input)?;
+        std::char::from_u32(c).ok_or_else(|| anyhow!("Invalid char {:#x}", c))
+    }
+}
+
+impl<N: BinaryTile> BinaryTile for Coord<N> {
+    const SIZE: usize = 2 * N::SIZE;
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        self.x.write_bytes(out);
+        self.y.write_bytes(out);
+    }
+
+    fn read_bytes(input: &mut &[u8]) -> Result<Self> {
+        let x = N::read_bytes(input)?;
+        let y = N::read_bytes(input)?;
+        Ok(Coord::new(x, y))
+    }
+}
+
+/// Number of tiles in a grid of the given size, if it is not empty and not too large.
+fn tile_count(width: i32, height: i32) -> Result<usize> {
+    ensure!(width > 0 && height > 0, "Empty grid {}x{}", width, height);
+    width
+        .checked_mul(height)
+        .map(|n| n as usize)
+        .with_context(|| format!("Grid {}x{} is too large", width, height))
+}
+
+fn escape_tile(tile: &str) -> String {
+    if tile.is_empty() {
+        return "\\e".to_string();
+    }
+    let mut escaped = String::with_capacity(tile.len());
+    for c in tile.chars() {
+        match c {
+            ' ' => escaped.push_str("\\s"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\\' => escaped.push_str("\\\\"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape_tile(tile: &str) -> Result<String> {
+    if tile == "\\e" {
+        return Ok(String::new());
+    }
+    let mut unescaped = String::with_capacity(tile.len());
+    let mut chars = tile.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        unescaped.push(match chars.next() {
+            Some('s') => ' ',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('\\') => '\\',
+            _ => bail!("Bad escape in tile '{}'", tile),
+        });
+    }
+    Ok(unescaped)
+}
+
+impl<T: Display> Grid<T> {
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}x{}\n", self.width, self.height);
+        for row in self.row_slices() {
+            text.push_str(&row.iter().map(|t| escape_tile(&t.to_string())).join(" "));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+impl<T: FromStr> Grid<T> {
+    pub fn from_text(text: &str) -> Result<Self> {
+        let mut lines = text.lines();
+        let header = lines.next().context("Missing grid header")?;
+        let (width, height) = header
+            .split('x')
+            .map(|n| n.parse::<i32>())
+            .collect_tuple()
+            .with_context(|| format!("Bad grid header '{}'", header))?;
+        let (width, height) = (width?, height?);
+        let count = tile_count(width, height)?;
+
+        let rows: Vec<Vec<&str>> = lines.map(|line| line.split(' ').collect()).collect();
+        ensure!(
+            rows.len() == height as usize,
+            "Expected {} rows, got {}",
+            height,
+            rows.len()
+        );
+        for (y, row) in rows.iter().enumerate() {
+            ensure!(
+                row.len() == width as usize,
+                "Row {} has {} tiles, expected {}",
+                y,
+                row.len(),
+                width
+            );
+        }
+        let mut tiles = Vec::with_capacity(count);
+        for (y, row) in rows.iter().enumerate() {
+            for tile in row {
+                let t = unescape_tile(tile)?
+                    .parse()
+                    .map_err(|_| anyhow!("Bad tile '{}' on row {}", tile, y))?;
+                tiles.push(t);
+            }
+        }
+        Ok(Grid::new(tiles, width, height))
+    }
+}
+
+impl<T: BinaryTile> Grid<T> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        (self.width as u32).write_bytes(&mut out);
+        (self.height as u32).write_bytes(&mut out);
+        for t in &self.tiles {
+            t.write_bytes(&mut out);
+        }
+        out
+    }
+
+    pub fn from_bytes(mut input: &[u8]) -> Result<Self> {
+        ensure!(take_bytes(&mut input, 4)? == MAGIC, "Not a binary grid");
+        let width = u32::read_bytes(&mut input)?;
+        let height = u32::read_bytes(&mut input)?;
+        let (width, height) = match (width.try_into(), height.try_into()) {
+            (Ok(w), Ok(h)) => (w, h),
+            _ => bail!("Grid {}x{} is too large", width, height),
+        };
+        let count = tile_count(width, height)?;
+        let expected = count
+            .checked_mul(T::SIZE)
+            .with_context(|| format!("Grid {}x{} is too large", width, height))?;
+        ensure!(
+            input.len() == expected,
+            "Expected {} bytes of tiles, got {}",
+            expected,
+            input.len()
+        );
+        let tiles = (0..count)
+            .map(|_| T::read_bytes(&mut input))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Grid::new(tiles, width, height))
+    }
+}
+
+/// Compares two grids in the text format row by row. Returns `None` if they are equal,
+/// otherwise a listing of the rows that differ.
+pub fn text_diff(expected: &str, actual: &str) -> Option<String> {
+    use itertools::EitherOrBoth::*;
+    let mut diff = String::new();
+    for (n, rows) in expected.lines().zip_longest(actual.lines()).enumerate() {
+        let (e, a) = match rows {
+            Both(e, a) if e == a => continue,
+            Both(e, a) => (e, a),
+            Left(e) => (e, ""),
+            Right(a) => ("", a),
+        };
+        diff.push_str(&format!("{:>4} - {}\n     + {}\n", n, e, a));
+    }
+    if diff.is_empty() {
+        None
+    } else {
+        Some(diff)
+    }
+}
+
+#[test]
+fn round_trip() {
+    let g = Grid::from_lines(["1234", "5678"], |c| c as u8 - b'0');
+    let text = g.to_text();
+    assert_eq!(text, "4x2\n1 2 3 4\n5 6 7 8\n");
+    assert_eq!(Grid::<u8>::from_text(&text).unwrap(), g);
+    assert_eq!(Grid::<u8>::from_bytes(&g.to_bytes()).unwrap(), g);
+    assert!(Grid::<u8>::from_text("4x2\n1 2 3 4\n").is_err());
+    assert!(Grid::<u8>::from_bytes(&g.to_bytes()[..10]).is_err());
+
+    let coords = Grid::new(vec![Coord::new(-1, 2), Coord::new(3, i32::MAX)], 2, 1);
+    assert_eq!(coords.to_text(), "2x1\n-1,2 3,2147483647\n");
+    let parsed = Grid::<Coord>::from_bytes(&coords.to_bytes()).unwrap();
+    assert_eq!(parsed.to_text(), coords.to_text());
+
+    let chars = Grid::from_lines(["a b", " \\\n"], |c| c);
+    let text = chars.to_text();
+    assert_eq!(text, "3x2\na \\s b\n\\s \\\\ \\n\n");
+    assert_eq!(Grid::<char>::from_text(&text).unwrap(), chars);
+    let strings = Grid::new(vec!["".to_string(), "x y".to_string()], 2, 1);
+    assert_eq!(strings.to_text(), "2x1\n\\e x\\sy\n");
+    assert_eq!(
+        Grid::<String>::from_text(&strings.to_text()).unwrap(),
+        strings
+    );
+    assert!(Grid::<char>::from_text("1x1\n\\q\n").is_err());
+
+    assert!(Grid::<u8>::from_text("65536x65536\n1\n").is_err());
+    assert!(Grid::<u8>::from_text("2x1\n1 x\n").is_err());
+    assert!(Grid::<Coord>::from_text("1x1\n1;2\n").is_err());
+    assert!(Grid::<Coord>::from_text("1x1\n1,z\n").is_err());
+    let mut huge = MAGIC.to_vec();
+    huge.extend_from_slice(&[0xff; 8]);
+    assert!(Grid::<u8>::from_bytes(&huge).is_err());
+    let mut huge = MAGIC.to_vec();
+    huge.extend_from_slice(&[0, 0, 1, 0, 0, 0, 1, 0, 0]);
+    assert!(Grid::<u8>::from_bytes(&huge).is_err());
+
+    let text = g.to_text();
+    let other = Grid::from_lines(["1234", "5698"], |c| c as u8 - b'0');
+    assert_eq!(text_diff(&text, &text), None);
+    assert_eq!(
+        text_diff(&text, &other.to_text()).unwrap(),
+        "   2 - 5 6 7 8\n     + 5 6 9 8\n"
+    );
+}
@@ -0,0 +1,134 @@
+use super::{Coord, Grid};
+
+use std::ops::{Deref, Index, IndexMut};
+
+/// A tile that differs between two grids, see `Grid::diff()`.
+#[derive(Debug, PartialEq)]
+pub struct Change<'a, T> {
+    pub coord: Coord,
+    pub old: &'a T,
+    pub new: &'a T,
+}
+
+impl<T: PartialEq> Grid<T> {
+    /// Returns an iterator over the tiles that differ between `self` and `new`, in row
+    /// order. The grids must have the same size.
+    pub fn diff<'a>(&'a self, new: &'a Self) -> impl Iterator<Item = Change<'a, T>> {
+        assert!(
+            self.width == new.width && self.height == new.height,
+            "Can't diff grids of different sizes."
+        );
+        self.iter_tiles()
+            .zip(new.tiles.iter())
+            .filter(|((_, old), new)| old != new)
+            .map(|((coord, old), new)| Change { coord, old, new })
+    }
+}
+
+/// Wrapper around a `Grid` that records which tiles have been accessed mutably, so that
+/// automata can process only the tiles that changed in the last step.
+///
+/// Tiles are marked as dirty on any mutable access, even if the value ends up unchanged.
+#[derive(Clone, Debug)]
+pub struct TrackedGrid<T> {
+    grid: Grid<T>,
+    is_dirty: Vec<bool>,
+    dirty: Vec<Coord>,
+}
+
+impl<T> TrackedGrid<T> {
+    pub fn new(grid: Grid<T>) -> Self {
+        Self {
+            is_dirty: vec![false; grid.tiles.len()],
+            dirty: Vec::new(),
+            grid,
+        }
+    }
+
+    fn mark_dirty(&mut self, coord: Coord) {
+        let idx = self.grid.coord_to_idx(coord);
+        if !self.is_dirty[idx] {
+            self.is_dirty[idx] = true;
+            self.dirty.push(coord);
+        }
+    }
+
+    pub fn get_mut(&mut self, coord: Coord) -> Option<&mut T> {
+        if !self.grid.valid_coord(coord) {
+            return None;
+        }
+        self.mark_dirty(coord);
+        self.grid.get_mut(coord)
+    }
+
+    /// The dirty tiles, in the order they were first modified.
+    pub fn dirty(&self) -> &[Coord] {
+        &self.dirty
+    }
+
+    /// Returns the dirty tiles and clears the dirty set.
+    pub fn take_dirty(&mut self) -> Vec<Coord> {
+        for c in &self.dirty {
+            self.is_dirty[self.grid.coord_to_idx(*c)] = false;
+        }
+        std::mem::take(&mut self.dirty)
+    }
+
+    pub fn into_inner(self) -> Grid<T> {
+        self.grid
+    }
+}
+
+impl<T> Deref for TrackedGrid<T> {
+    type Target = Grid<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.grid
+    }
+}
+
+impl<T> Index<Coord> for TrackedGrid<T> {
+    type Output = T;
+
+    fn index(&self, index: Coord) -> &Self::Output {
+        &self.grid[index]
+    }
+}
+
+impl<T> IndexMut<Coord> for TrackedGrid<T> {
+    fn index_mut(&mut self, index: Coord) -> &mut Self::Output {
+        self.mark_dirty(index);
+        &mut self.grid[index]
+    }
+}
+
+#[test]
+fn diff_and_tracking() {
+    let a = Grid::from_lines(["abc", "def"], |c| c);
+    let mut b = TrackedGrid::new(a.clone());
+    b[Coord::new(1, 1)] = 'x';
+    *b.get_mut(Coord::new(0, 0)).unwrap() = 'y';
+    b[Coord::new(1, 1)] = 'z';
+    assert!(b.get_mut(Coord::new(3, 0)).is_none());
+    assert_eq!(b.dirty(), [Coord::new(1, 1), Coord::new(0, 0)]);
+    assert_eq!(
+        a.diff(&b).collect::<Vec<_>>(),
+        [
+            Change {
+                coord: Coord::new(0, 0),
+                old: &'a',
+                new: &'y'
+            },
+            Change {
+                coord: Coord::new(1, 1),
+                old: &'e',
+                new: &'z'
+            }
+        ]
+    );
+    assert_eq!(b.take_dirty().len(), 2);
+    assert!(b.dirty().is_empty());
+    b[Coord::new(1, 1)] = 'e';
+    assert_eq!(b.dirty(), [Coord::new(1, 1)]);
+    assert_ne!(a, Grid::from_lines(["ab", "cd", "ef"], |c| c));
+}
@@ -8,6 +8,7 @@ use std::io::{self, BufRead, BufReader};
 use std::iter::FromIterator;
 use std::path::Path;
 
+pub mod cfg;
 pub mod grid;
 
 pub mod y2019;
@@ -1,15 +1,20 @@
-use crate::grid::Grid;
+use crate::grid::{Coord, Grid};
 use crate::y2019::intcode::Intcode;
 
+use itertools::Itertools;
+
 fn load_input<L: IntoIterator<Item = S>, S: AsRef<str>>(line_source: L) -> Intcode {
     Intcode::load_program(line_source.into_iter().next().unwrap().as_ref())
 }
 
-fn part1(pre_run: &Intcode) -> isize {
+fn scaffold(pre_run: &Intcode) -> Grid<char> {
     let mut intcode = pre_run.clone();
-    let r = intcode.run_until_end(&[]);
-    let scaffold: String = r.iter().map(|&m| m as u8 as char).collect();
-    let scaffold = Grid::from_lines(scaffold.trim_end().lines(), |c| c);
+    let (text, _) = intcode.read_text().unwrap();
+    Grid::from_lines(text.trim_end().lines(), |c| c)
+}
+
+fn part1(pre_run: &Intcode) -> isize {
+    let scaffold = scaffold(pre_run);
 
     println!("{}", scaffold);
 
@@ -23,15 +28,115 @@ fn part1(pre_run: &Intcode) -> isize {
     tot_align as isize
 }
 
+/// Max length of a movement routine or function, not counting the newline.
+const MAX_LEN: usize = 20;
+
+/// Follows the scaffold from the robot to the end, turning only at corners. If the robot
+/// already faces along the scaffold, the first move is a forward run without a turn.
+fn path(scaffold: &Grid<char>) -> Vec<String> {
+    let is_scaffold = |c| scaffold.get(c).is_some_and(|&t| t != '.');
+    let (mut pos, dir) = scaffold
+        .iter_tiles()
+        .find(|(_, &t)| "^v<>".contains(t))
+        .unwrap();
+    let mut dir = match dir {
+        '^' => Coord::new(0, -1),
+        'v' => Coord::new(0, 1),
+        '<' => Coord::new(-1, 0),
+        _ => Coord::new(1, 0),
+    };
+    let mut moves = vec![];
+    loop {
+        let turn = if is_scaffold(pos + dir) {
+            None
+        } else if is_scaffold(pos + dir.rotate_ccw()) {
+            dir = dir.rotate_ccw();
+            Some('L')
+        } else if is_scaffold(pos + dir.rotate_cw()) {
+            dir = dir.rotate_cw();
+            Some('R')
+        } else {
+            break;
+        };
+        let mut steps = 0;
+        while is_scaffold(pos + dir) {
+            pos += dir;
+            steps += 1;
+        }
+        moves.push(match turn {
+            Some(turn) => format!("{},{}", turn, steps),
+            None => steps.to_string(),
+        });
+    }
+    moves
+}
+
+/// Splits `moves` into calls to at most three functions.
+fn compress<'a>(
+    moves: &'a [String],
+    funcs: &mut Vec<&'a [String]>,
+    routine: &mut Vec<usize>,
+) -> bool {
+    if moves.is_empty() {
+        return true;
+    }
+    if routine.len() * 2 + 1 > MAX_LEN {
+        return false;
+    }
+    for f in 0..funcs.len() {
+        if moves.starts_with(funcs[f]) {
+            routine.push(f);
+            if compress(&moves[funcs[f].len()..], funcs, routine) {
+                return true;
+            }
+            routine.pop();
+        }
+    }
+    if funcs.len() < 3 {
+        for len in 1..=moves.len() {
+            if moves[..len].join(",").len() > MAX_LEN {
+                break;
+            }
+            funcs.push(&moves[..len]);
+            routine.push(funcs.len() - 1);
+            if compress(&moves[len..], funcs, routine) {
+                return true;
+            }
+            routine.pop();
+            funcs.pop();
+        }
+    }
+    false
+}
+
 fn part2(pre_run: &Intcode) -> isize {
+    let moves = path(&scaffold(pre_run));
+    let mut funcs = vec![];
+    let mut routine = vec![];
+    assert!(compress(&moves, &mut funcs, &mut routine));
+
     let mut intcode = pre_run.clone();
     intcode.poke(0, 2);
-    intcode.run_until_end(&[])[0]
+    intcode.send_line(&routine.iter().map(|&f| (b'A' + f as u8) as char).join(","));
+    for f in 0..3 {
+        intcode.send_line(&funcs.get(f).map(|f| f.join(",")).unwrap_or_default());
+    }
+    intcode.send_line("n");
+    intcode.read_text().unwrap().1.unwrap()
 }
 
 #[test]
 fn real_data() {
     let d = load_input(crate::load_strings(crate::data_file!()));
     assert_eq!(part1(&d), 7280);
-    // assert_eq!(part2(&d), 1);
+    assert_eq!(part2(&d), 1045393);
+}
+
+#[test]
+fn test_path() {
+    let scaffold = |rows: &[&str]| Grid::from_lines(rows.iter(), |c| c);
+    let turn_first = scaffold(&["...#", "...#", "...#", "^###"]);
+    assert_eq!(path(&turn_first), ["R,3", "L,3"]);
+    let forward_first = scaffold(&["...#", "...#", "...#", ">###"]);
+    assert_eq!(path(&forward_first), ["3", "L,3"]);
 }
@@ -1,3 +1,4 @@
+use crate::y2019::intcode::cache::DecodeCache;
 use crate::y2019::intcode::Intcode;
 
 fn load_input<L: IntoIterator<Item = S>, S: AsRef<str>>(line_source: L) -> Intcode {
@@ -6,17 +7,18 @@ fn load_input<L: IntoIterator<Item = S>, S: AsRef<str>>(line_source: L) -> Intco
 
 fn part1(intcode: &Intcode) -> usize {
     let mut intcode = intcode.clone();
-    intcode.run_until_end(&[]);
+    intcode.run_until_end(&[]).unwrap();
     intcode.peek(0) as usize
 }
 
 fn part2(pre_run: &Intcode) -> isize {
+    let mut cache = DecodeCache::new();
     for verb in 0..100 {
         for noun in 0..100 {
             let mut intcode = pre_run.clone();
             intcode.poke(1, noun);
             intcode.poke(2, verb);
-            intcode.run_until_end(&[]);
+            intcode.run_until_end_cached(&[], &mut cache).unwrap();
             if intcode.peek(0) == 19690720 {
                 return 100 * noun + verb;
             }
@@ -6,13 +6,13 @@ fn load_input<L: IntoIterator<Item = S>, S: AsRef<str>>(line_source: L) -> Intco
 
 fn part1(pre_run: &Intcode) -> isize {
     let mut intcode = pre_run.clone();
-    let r = intcode.run_until_end(&[1]);
+    let r = intcode.run_until_end(&[1]).unwrap();
     *r.last().unwrap()
 }
 
 fn part2(pre_run: &Intcode) -> isize {
     let mut intcode = pre_run.clone();
-    intcode.run_until_end(&[5])[0]
+    intcode.run_until_end(&[5]).unwrap()[0]
 }
 
 #[test]
@@ -25,18 +25,52 @@ fn real_data() {
 #[test]
 fn test_data() {
     // test eq
-    let m = Intcode::load_program("3,9,8,9,10,9,4,9,99,-1,8");
+    let m = Intcode::from_asm(
+        "
+        in [x]
+        eq [x], [eight], [x]
+        out [x]
+        hlt
+x:      .data -1
+eight:  .data 8",
+    )
+    .unwrap();
+    assert_eq!(m, Intcode::load_program("3,9,8,9,10,9,4,9,99,-1,8"));
     for (i, o) in [(8, 1), (9, 0)] {
-        assert_eq!(m.clone().run_until_end(&[i])[0], o);
+        assert_eq!(m.clone().run_until_end(&[i]).unwrap()[0], o);
     }
-    // test eq imm
-    let m = Intcode::load_program("3,3,1108,-1,8,3,4,3,99");
+    // test eq imm, the input overwrites the first operand of eq
+    let m = Intcode::from_asm(
+        "
+        in [3]
+        eq #-1, #8, [3]
+        out [3]
+        hlt",
+    )
+    .unwrap();
+    assert_eq!(m, Intcode::load_program("3,3,1108,-1,8,3,4,3,99"));
     for (i, o) in [(8, 1), (9, 0)] {
-        assert_eq!(m.clone().run_until_end(&[i])[0], o);
+        assert_eq!(m.clone().run_until_end(&[i]).unwrap()[0], o);
     }
     // jump pos
-    let m = Intcode::load_program("3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9");
+    let m = Intcode::from_asm(
+        "
+        in [x]
+        jz [x], [target]
+        add [r], [one], [r]
+done:   out [r]
+        hlt
+x:      .data -1
+r:      .data 0
+one:    .data 1
+target: .data done",
+    )
+    .unwrap();
+    assert_eq!(
+        m,
+        Intcode::load_program("3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9")
+    );
     for (i, o) in [(8, 1), (0, 0)] {
-        assert_eq!(m.clone().run_until_end(&[i])[0], o);
+        assert_eq!(m.clone().run_until_end(&[i]).unwrap()[0], o);
     }
 }
@@ -6,13 +6,13 @@ fn load_input<L: IntoIterator<Item = S>, S: AsRef<str>>(line_source: L) -> Intco
 
 fn part1(pre_run: &Intcode) -> isize {
     let mut intcode = pre_run.clone();
-    let r = intcode.run_until_end(&[1]);
+    let r = intcode.run_until_end(&[1]).unwrap();
     *r.last().unwrap()
 }
 
 fn part2(pre_run: &Intcode) -> isize {
     let mut intcode = pre_run.clone();
-    intcode.run_until_end(&[2])[0]
+    intcode.run_until_end(&[2]).unwrap()[0]
 }
 
 #[test]
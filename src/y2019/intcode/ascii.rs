@@ -0,0 +1,157 @@
+//! Text I/O for Intcode programs that talk ASCII, like the vacuum robot in 2019 day 17.
+//!
+//! Input lines are queued on the VM and consumed as the program reads them. Output is read
+//! a line at a time. Values outside the ASCII range end the line, since that is how these
+//! programs report their final answer.
+
+use super::{Intcode, IntcodeError, MemCell, PauseCause};
+
+use anyhow::{bail, Result};
+
+use std::convert::TryFrom;
+use std::io::{BufRead, Write};
+
+/// What ended a line of output.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LineEnd {
+    Newline,
+    /// A value outside the ASCII range.
+    Value(MemCell),
+    /// The program is waiting for input, so the text is usually a prompt.
+    NeedInput,
+    Halt,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsciiLine {
+    pub text: String,
+    pub end: LineEnd,
+}
+
+impl Intcode {
+    /// Queues a line of text as input, adding the newline.
+    pub fn send_line(&mut self, line: &str) {
+        for b in line.bytes().chain(Some(b'\n')) {
+            self.push_input(b as MemCell);
+        }
+    }
+
+    /// Runs until the program has written a full line, produced a non-ASCII value, halted
+    /// or needs more input than has been queued.
+    pub fn read_line(&mut self) -> Result<AsciiLine, IntcodeError> {
+        let mut text = String::new();
+        let end = loop {
+            if self.wants_input() {
+                break LineEnd::NeedInput;
+            }
+            match self.step()? {
+                None => {}
+                Some(PauseCause::Halt) => break LineEnd::Halt,
+                Some(PauseCause::Output(10)) => break LineEnd::Newline,
+                Some(PauseCause::Output(val)) => match u8::try_from(val) {
+                    Ok(b) if b.is_ascii() => text.push(b as char),
+                    _ => break LineEnd::Value(val),
+                },
+                Some(PauseCause::Input(_)) => unreachable!("Checked by wants_input()"),
+            }
+        };
+        Ok(AsciiLine { text, end })
+    }
+
+    /// Reads output until the program halts or needs input. Returns the text and the last
+    /// non-ASCII value, if there was one.
+    pub fn read_text(&mut self) -> Result<(String, Option<MemCell>), IntcodeError> {
+        let mut text = String::new();
+        let mut value = None;
+        loop {
+            let line = self.read_line()?;
+            text += &line.text;
+            match line.end {
+                LineEnd::Newline => text.push('\n'),
+                LineEnd::Value(val) => value = Some(val),
+                LineEnd::NeedInput | LineEnd::Halt => return Ok((text, value)),
+            }
+        }
+    }
+
+    /// Runs the program as an interactive session, writing its output to `output` and
+    /// reading a line from `input` whenever it waits for input. For a terminal session,
+    /// pass `std::io::stdin().lock()` and `std::io::stdout()`. Returns the last non-ASCII
+    /// value once the program halts.
+    pub fn interactive<R: BufRead, W: Write>(
+        &mut self,
+        mut input: R,
+        mut output: W,
+    ) -> Result<Option<MemCell>> {
+        let mut value = None;
+        loop {
+            let line = self.read_line()?;
+            write!(output, "{}", line.text)?;
+            match line.end {
+                LineEnd::Newline => writeln!(output)?,
+                LineEnd::Value(val) => {
+                    if !line.text.is_empty() {
+                        writeln!(output)?;
+                    }
+                    writeln!(output, "{}", val)?;
+                    value = Some(val);
+                }
+                LineEnd::NeedInput => {
+                    output.flush()?;
+                    let mut buf = String::new();
+                    if input.read_line(&mut buf)? == 0 {
+                        bail!("Input closed while the program is waiting for input");
+                    }
+                    self.send_line(buf.trim_end_matches(&['\r', '\n'][..]));
+                }
+                LineEnd::Halt => {
+                    if !line.text.is_empty() {
+                        writeln!(output)?;
+                    }
+                    return Ok(value);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn echo() {
+    let prog = Intcode::from_asm(
+        "
+        out #72
+        out #105
+        out #10
+loop:   in [c]
+        eq [c], #10, [t]
+        jnz [t], #done
+        out [c]
+        jz #0, #loop
+done:   out #1000
+        hlt
+c:      .data 0
+t:      .data 0",
+    )
+    .unwrap();
+    let line = |text: &str, end| AsciiLine {
+        text: text.to_string(),
+        end,
+    };
+
+    let mut vm = prog.clone();
+    assert_eq!(vm.read_line().unwrap(), line("Hi", LineEnd::Newline));
+    assert_eq!(vm.read_line().unwrap(), line("", LineEnd::NeedInput));
+    vm.send_line("ok");
+    assert_eq!(vm.read_line().unwrap(), line("ok", LineEnd::Value(1000)));
+    assert_eq!(vm.read_line().unwrap(), line("", LineEnd::Halt));
+
+    let mut vm = prog.clone();
+    vm.send_line("abc");
+    assert_eq!(vm.read_text().unwrap(), ("Hi\nabc".to_string(), Some(1000)));
+
+    let mut out = vec![];
+    let mut vm = prog.clone();
+    assert_eq!(vm.interactive(&b"xyz\n"[..], &mut out).unwrap(), Some(1000));
+    assert_eq!(String::from_utf8(out).unwrap(), "Hi\nxyz\n1000\n");
+    assert!(prog.clone().interactive(&b""[..], vec![]).is_err());
+}
@@ -0,0 +1,230 @@
+//! Assembler for the Intcode listing format produced by `disasm`.
+//!
+//! Each line is `[addr:] [label:] [statement] [; comment]`. The optional address column
+//! is checked against the actual address, so a disassembly listing assembles back to the
+//! exact same memory image. Statements are instructions like `add [9], #3, rb-2`, or the
+//! data directives `.data 1, -2, label` and `.zero <count>`. Immediate and position
+//! parameters can refer to labels, as in `jz [7], #loop` or `out [result]`.
+
+use super::{encode_opcode, Intcode, MemCell, Mode, Op};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+use std::collections::HashMap;
+
+enum Value {
+    Num(MemCell),
+    Label(String),
+}
+
+enum Statement {
+    Instr(MemCell, Vec<(Mode, Value)>),
+    Data(Vec<Value>),
+    Zero(usize),
+}
+
+impl Statement {
+    fn len(&self) -> usize {
+        match self {
+            Statement::Instr(_, params) => params.len() + 1,
+            Statement::Data(values) => values.len(),
+            Statement::Zero(n) => *n,
+        }
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_value(s: &str) -> Result<Value> {
+    let s = s.trim();
+    if let Ok(n) = s.parse() {
+        Ok(Value::Num(n))
+    } else if is_ident(s) {
+        Ok(Value::Label(s.to_string()))
+    } else {
+        bail!("Bad value '{}'", s)
+    }
+}
+
+fn parse_param(s: &str) -> Result<(Mode, Value)> {
+    let s = s.trim();
+    if let Some(imm) = s.strip_prefix('#') {
+        Ok((Mode::Imm, parse_value(imm)?))
+    } else if let Some(pos) = s.strip_prefix('[').and_then(|p| p.strip_suffix(']')) {
+        Ok((Mode::Pos, parse_value(pos)?))
+    } else if let Some(off) = s.strip_prefix("rb") {
+        let off = off.trim().strip_prefix('+').unwrap_or(off).trim();
+        let off = if off.is_empty() { 0 } else { off.parse()? };
+        Ok((Mode::Rel, Value::Num(off)))
+    } else {
+        bail!("Bad parameter '{}'", s)
+    }
+}
+
+fn parse_statement(s: &str) -> Result<Statement> {
+    let (word, args) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+    let args = args.trim();
+    let list = || args.split(',').filter(|a| !a.trim().is_empty());
+    Ok(match word {
+        ".data" => Statement::Data(list().map(parse_value).collect::<Result<_>>()?),
+        ".zero" => Statement::Zero(args.parse()?),
+        _ => {
+            let op =
+                Op::from_mnemonic(word).ok_or_else(|| anyhow!("Unknown mnemonic '{}'", word))?;
+            let params = list().map(parse_param).collect::<Result<Vec<_>>>()?;
+            ensure!(
+                params.len() == op.arity(),
+                "'{}' takes {} parameters, got {}",
+                word,
+                op.arity(),
+                params.len()
+            );
+            Statement::Instr(op.code(), params)
+        }
+    })
+}
+
+/// Parses a source line, defining its label if it has one.
+fn parse_line(
+    line: &str,
+    addr: usize,
+    labels: &mut HashMap<String, usize>,
+) -> Result<Option<Statement>> {
+    let mut rest = line.split(';').next().unwrap().trim();
+    if let Some((col, tail)) = rest.split_once(':') {
+        if let Ok(col_addr) = col.trim().parse::<usize>() {
+            ensure!(
+                col_addr == addr,
+                "Address column {} should be {}",
+                col,
+                addr
+            );
+            rest = tail.trim();
+        }
+    }
+    if let Some((label, tail)) = rest.split_once(':') {
+        let label = label.trim();
+        if is_ident(label) {
+            ensure!(
+                labels.insert(label.to_string(), addr).is_none(),
+                "Label '{}' defined twice",
+                label
+            );
+            rest = tail.trim();
+        }
+    }
+    if rest.is_empty() {
+        return Ok(None);
+    }
+    parse_statement(rest).map(Some)
+}
+
+fn resolve(v: &Value, labels: &HashMap<String, usize>) -> Result<MemCell> {
+    match v {
+        Value::Num(n) => Ok(*n),
+        Value::Label(l) => labels
+            .get(l)
+            .map(|&a| a as MemCell)
+            .ok_or_else(|| anyhow!("Undefined label '{}'", l)),
+    }
+}
+
+fn emit(stmt: &Statement, labels: &HashMap<String, usize>, mem: &mut Vec<MemCell>) -> Result<()> {
+    match stmt {
+        Statement::Instr(op, params) => {
+            let modes: Vec<_> = params.iter().map(|(m, _)| *m).collect();
+            mem.push(encode_opcode(*op, &modes));
+            for (_, v) in params {
+                mem.push(resolve(v, labels)?);
+            }
+        }
+        Statement::Data(values) => {
+            for v in values {
+                mem.push(resolve(v, labels)?);
+            }
+        }
+        Statement::Zero(count) => mem.resize(mem.len() + count, 0),
+    }
+    Ok(())
+}
+
+/// Assembles a program into a memory image.
+pub fn assemble(src: &str) -> Result<Vec<MemCell>> {
+    let mut statements = vec![];
+    let mut labels = HashMap::new();
+    let mut addr = 0;
+    for (n, line) in src.lines().enumerate() {
+        let stmt = parse_line(line, addr, &mut labels)
+            .with_context(|| format!("Line {}: '{}'", n + 1, line))?;
+        if let Some(stmt) = stmt {
+            addr += stmt.len();
+            statements.push((n, stmt));
+        }
+    }
+
+    let mut mem = Vec::with_capacity(addr);
+    for (n, stmt) in statements {
+        emit(&stmt, &labels, &mut mem).with_context(|| format!("Line {}", n + 1))?;
+    }
+    Ok(mem)
+}
+
+impl Intcode {
+    /// Loads a program written in the assembler syntax.
+    pub fn from_asm(src: &str) -> Result<Self> {
+        Ok(Self::new(assemble(src)?))
+    }
+}
+
+#[test]
+fn readable_program() {
+    let prog = "
+        in [x]              ; output 1 if the input is 8
+        eq [x], [eight], [x]
+        out [x]
+        hlt
+x:      .data -1
+eight:  .data 8";
+    assert_eq!(
+        assemble(prog).unwrap(),
+        [3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]
+    );
+    let m = Intcode::from_asm(prog).unwrap();
+    assert_eq!(m.clone().run_until_end(&[8]).unwrap(), [1]);
+    assert_eq!(m.clone().run_until_end(&[7]).unwrap(), [0]);
+
+    let prog = "
+start:  arb #3
+        add #start, #0, rb-1
+        jnz #1, #end
+        .zero 2
+end:    hlt";
+    assert_eq!(
+        assemble(prog).unwrap(),
+        [109, 3, 21101, 0, 0, -1, 1105, 1, 11, 0, 0, 99]
+    );
+    assert!(assemble("jz #1").is_err());
+    assert!(assemble("jz #1, #nowhere").is_err());
+    assert!(assemble("5: hlt").is_err());
+}
+
+#[test]
+fn round_trip() {
+    for day in [2, 5, 7, 9, 17] {
+        let file = format!("data/2019/day{}.txt", day);
+        let prog = Intcode::load_program(&crate::load_strings(file).next().unwrap());
+        let listing = prog.disassemble().to_string();
+        assert_eq!(
+            assemble(&listing).unwrap(),
+            prog.memory().to_vec(),
+            "day {}",
+            day
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! Memory cell types for the Intcode VM.
+//!
+//! The VM is generic over its cell type. Arithmetic is always checked, so a result that
+//! doesn't fit in the cell is reported as `ErrorKind::Overflow` instead of wrapping. `isize`
+//! is the default, `i128` gives more headroom and `BigCell` never overflows.
+
+use anyhow::{anyhow, bail};
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::Hash;
+use std::str::FromStr;
+
+pub trait Cell: Clone + Debug + Default + Display + Ord + Hash + FromStr {
+    fn from_isize(n: isize) -> Self;
+    /// Converts to `isize`, if the value fits.
+    fn to_isize(&self) -> Option<isize>;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn from_bool(b: bool) -> Self {
+        Self::from_isize(b as isize)
+    }
+}
+
+macro_rules! impl_cell {
+    ($($t:ty),*) => {$(
+        impl Cell for $t {
+            #[inline(always)]
+            fn from_isize(n: isize) -> Self {
+                n as $t
+            }
+
+            #[inline(always)]
+            fn to_isize(&self) -> Option<isize> {
+                isize::try_from(*self).ok()
+            }
+
+            #[inline(always)]
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                <$t>::checked_add(*self, *other)
+            }
+
+            #[inline(always)]
+            fn checked_mul(&self, other: &Self) -> Option<Self> {
+                <$t>::checked_mul(*self, *other)
+            }
+        }
+    )*};
+}
+
+impl_cell!(isize, i128);
+
+/// Arbitrary precision integer, stored as a sign and base 2^32 digits, least significant
+/// first. Zero has no digits and is never negative.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BigCell {
+    neg: bool,
+    mag: Vec<u32>,
+}
+
+fn trim(mag: &mut Vec<u32>) {
+    while mag.last() == Some(&0) {
+        mag.pop();
+    }
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for n in 0..a.len().max(b.len()) {
+        let s = *a.get(n).unwrap_or(&0) as u64 + *b.get(n).unwrap_or(&0) as u64 + carry;
+        sum.push(s as u32);
+        carry = s >> 32;
+    }
+    sum.push(carry as u32);
+    trim(&mut sum);
+    sum
+}
+
+/// `a - b`, where `a >= b`.
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut diff = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (n, &d) in a.iter().enumerate() {
+        let s = d as i64 - *b.get(n).unwrap_or(&0) as i64 - borrow;
+        diff.push(s.rem_euclid(1 << 32) as u32);
+        borrow = (s < 0) as i64;
+    }
+    trim(&mut diff);
+    diff
+}
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut prod = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let p = x as u64 * y as u64 + prod[i + j] as u64 + carry;
+            prod[i + j] = p as u32;
+            carry = p >> 32;
+        }
+        prod[i + b.len()] = carry as u32;
+    }
+    trim(&mut prod);
+    prod
+}
+
+impl BigCell {
+    fn new(neg: bool, mut mag: Vec<u32>) -> Self {
+        trim(&mut mag);
+        Self {
+            neg: neg && !mag.is_empty(),
+            mag,
+        }
+    }
+
+    /// Divides the magnitude by `div` in place, returning the remainder.
+    fn div_rem_small(mag: &mut Vec<u32>, div: u32) -> u32 {
+        let mut rem = 0u64;
+        for d in mag.iter_mut().rev() {
+            let cur = (rem << 32) | *d as u64;
+            *d = (cur / div as u64) as u32;
+            rem = cur % div as u64;
+        }
+        trim(mag);
+        rem as u32
+    }
+}
+
+impl From<i128> for BigCell {
+    fn from(n: i128) -> Self {
+        let abs = n.unsigned_abs();
+        let mag = (0..4).map(|n| (abs >> (32 * n)) as u32).collect();
+        Self::new(n < 0, mag)
+    }
+}
+
+impl Cell for BigCell {
+    fn from_isize(n: isize) -> Self {
+        Self::from(n as i128)
+    }
+
+    fn to_isize(&self) -> Option<isize> {
+        if self.mag.len() > 2 {
+            return None;
+        }
+        let abs = self
+            .mag
+            .iter()
+            .rev()
+            .fold(0i128, |acc, &d| (acc << 32) | d as i128);
+        isize::try_from(if self.neg { -abs } else { abs }).ok()
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(if self.neg == other.neg {
+            Self::new(self.neg, add_mag(&self.mag, &other.mag))
+        } else {
+            match cmp_mag(&self.mag, &other.mag) {
+                Ordering::Less => Self::new(other.neg, sub_mag(&other.mag, &self.mag)),
+                _ => Self::new(self.neg, sub_mag(&self.mag, &other.mag)),
+            }
+        })
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(Self::new(
+            self.neg != other.neg,
+            mul_mag(&self.mag, &other.mag),
+        ))
+    }
+}
+
+impl Ord for BigCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.neg, other.neg) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.mag, &other.mag),
+            (true, true) => cmp_mag(&other.mag, &self.mag),
+        }
+    }
+}
+
+impl PartialOrd for BigCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+const DECIMAL_CHUNK: u32 = 1_000_000_000;
+
+impl Display for BigCell {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut mag = self.mag.clone();
+        let mut chunks = vec![];
+        while !mag.is_empty() {
+            chunks.push(Self::div_rem_small(&mut mag, DECIMAL_CHUNK));
+        }
+        let mut s = match chunks.pop() {
+            Some(top) => top.to_string(),
+            None => "0".to_string(),
+        };
+        for chunk in chunks.iter().rev() {
+            s += &format!("{:09}", chunk);
+        }
+        f.pad_integral(!self.neg, "", &s)
+    }
+}
+
+impl FromStr for BigCell {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (neg, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        if digits.is_empty() {
+            bail!("No digits in '{}'", s);
+        }
+        let ten = Self::from(10);
+        let mut val = Self::default();
+        for c in digits.chars() {
+            let d = c
+                .to_digit(10)
+                .ok_or_else(|| anyhow!("Bad digit '{}' in '{}'", c, s))?;
+            val = val.checked_mul(&ten).unwrap();
+            val = val.checked_add(&Self::from(d as i128)).unwrap();
+        }
+        Ok(Self::new(neg, val.mag))
+    }
+}
+
+#[test]
+fn big_cell() {
+    let big = |s: &str| s.parse::<BigCell>().unwrap();
+    let a = big("-123456789012345678901234567890");
+    let b = big("98765432109876543210");
+    assert_eq!(a.to_string(), "-123456789012345678901234567890");
+    assert_eq!(
+        a.checked_add(&b).unwrap().to_string(),
+        "-123456788913580246791358024680"
+    );
+    assert_eq!(
+        a.checked_mul(&b).unwrap().to_string(),
+        "-12193263113702179522496570642237463801111263526900"
+    );
+    assert_eq!(
+        a.checked_add(&big("123456789012345678901234567890")),
+        Some(BigCell::default())
+    );
+    assert!(a < b && BigCell::from(-1) < BigCell::default());
+    assert_eq!(BigCell::from_isize(isize::MIN).to_isize(), Some(isize::MIN));
+    assert_eq!(b.to_isize(), None);
+    assert_eq!(big("-0"), BigCell::default());
+    assert!("1-2".parse::<BigCell>().is_err());
+}
@@ -0,0 +1,251 @@
+//! Step-through debugging of Intcode programs, with an instruction trace, breakpoints and
+//! watchpoints on memory writes.
+//!
+//! The debugger executes one instruction at a time and decodes each instruction before
+//! running it, so it is a lot slower than `Intcode::run()`, which is left untouched.
+
+use super::disasm::Instruction;
+use super::{Event, Intcode, IntcodeError, MemCell, PauseCause};
+
+use itertools::Itertools;
+
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+
+/// An executed instruction, as reported to the trace hook.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub ip: usize,
+    pub rel_base: isize,
+    pub instr: Instruction,
+    /// The parameter values before the instruction is executed. For the parameter that is
+    /// written to, this is the target address.
+    pub values: Vec<MemCell>,
+}
+
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let instr = self.instr.to_string();
+        write!(f, "{:>5}: {:<28}", self.ip, instr)?;
+        if !self.values.is_empty() {
+            write!(f, "; {}", self.values.iter().join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+pub enum Stop<'a> {
+    /// The VM paused as it would in `Intcode::run()`.
+    Pause(PauseCause<'a>),
+    /// The next instruction is at a breakpoint.
+    Breakpoint(usize),
+    /// The instruction at `ip` wrote to a watched address.
+    Watchpoint {
+        ip: usize,
+        addr: usize,
+        old: MemCell,
+        new: MemCell,
+    },
+    /// A single step finished without any of the above happening.
+    Stepped,
+}
+
+/// Outcome of a step, without any borrows of the VM.
+enum Outcome {
+    Event(Event),
+    Watch {
+        ip: usize,
+        addr: usize,
+        old: MemCell,
+        new: MemCell,
+    },
+}
+
+type TraceHook<'h> = Box<dyn FnMut(&TraceEntry) + 'h>;
+
+pub struct Debugger<'h> {
+    pub vm: Intcode,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<usize>,
+    trace: Option<TraceHook<'h>>,
+}
+
+impl<'h> Debugger<'h> {
+    pub fn new(vm: Intcode) -> Self {
+        Self {
+            vm,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            trace: None,
+        }
+    }
+
+    /// Sets a hook that is called before each instruction is executed.
+    pub fn set_trace<F: FnMut(&TraceEntry) + 'h>(&mut self, hook: F) {
+        self.trace = Some(Box::new(hook));
+    }
+
+    pub fn clear_trace(&mut self) {
+        self.trace = None;
+    }
+
+    pub fn add_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    /// Stops execution after any instruction that writes to `addr`. Writes done by the
+    /// caller through `PauseCause::Input` aren't reported.
+    pub fn add_watchpoint(&mut self, addr: usize) {
+        self.watchpoints.insert(addr);
+    }
+
+    pub fn remove_watchpoint(&mut self, addr: usize) -> bool {
+        self.watchpoints.remove(&addr)
+    }
+
+    pub fn into_inner(self) -> Intcode {
+        self.vm
+    }
+
+    /// Executes a single instruction, ignoring any breakpoint at the current address.
+    pub fn step(&mut self) -> Result<Stop<'_>, IntcodeError> {
+        let outcome = self.step_outcome()?;
+        Ok(self.make_stop(outcome))
+    }
+
+    /// Runs until the VM pauses or a breakpoint or watchpoint is hit. A breakpoint at the
+    /// current address is stepped over, so `run()` can be called again to continue.
+    pub fn run(&mut self) -> Result<Stop<'_>, IntcodeError> {
+        let outcome = loop {
+            match self.step_outcome()? {
+                Outcome::Event(Event::Continue) => {}
+                outcome => break outcome,
+            }
+            if self.breakpoints.contains(&self.vm.ip) {
+                return Ok(Stop::Breakpoint(self.vm.ip));
+            }
+        };
+        Ok(self.make_stop(outcome))
+    }
+
+    fn make_stop(&mut self, outcome: Outcome) -> Stop<'_> {
+        match outcome {
+            Outcome::Event(event) => self.vm.pause(event).map_or(Stop::Stepped, Stop::Pause),
+            Outcome::Watch { ip, addr, old, new } => Stop::Watchpoint { ip, addr, old, new },
+        }
+    }
+
+    fn read(&self, addr: usize) -> MemCell {
+        self.vm.mem.get(addr)
+    }
+
+    fn trace_entry(&self, instr: Instruction) -> TraceEntry {
+        let write = instr.write_param();
+        let values = instr
+            .params
+            .iter()
+            .enumerate()
+            .map(|(n, p)| match (self.vm.param_addr(p), Some(n) == write) {
+                (None, _) => p.value,
+                (Some(addr), true) => addr as MemCell,
+                (Some(addr), false) => self.read(addr),
+            })
+            .collect();
+        TraceEntry {
+            ip: self.vm.ip,
+            rel_base: self.vm.rel_base,
+            instr,
+            values,
+        }
+    }
+
+    fn step_outcome(&mut self) -> Result<Outcome, IntcodeError> {
+        let ip = self.vm.ip;
+        let mut watched = None;
+        if let Some(instr) = self.vm.decode_next() {
+            if let Some(n) = instr.write_param() {
+                watched = self
+                    .vm
+                    .param_addr(&instr.params[n])
+                    .filter(|addr| self.watchpoints.contains(addr))
+                    .map(|addr| (addr, self.read(addr)));
+            }
+            if let Some(mut hook) = self.trace.take() {
+                hook(&self.trace_entry(instr));
+                self.trace = Some(hook);
+            }
+        }
+        let event = self.vm.exec()?;
+        if let (Event::Continue, Some((addr, old))) = (&event, watched) {
+            let new = self.read(addr);
+            return Ok(Outcome::Watch { ip, addr, old, new });
+        }
+        Ok(Outcome::Event(event))
+    }
+}
+
+#[test]
+fn step_through() {
+    let prog = Intcode::from_asm(
+        "
+        in [count]
+loop:   add [total], [count], [total]
+        add [count], #-1, [count]
+        jnz [count], #loop
+        out [total]
+        hlt
+count:  .data 0
+total:  .data 0",
+    )
+    .unwrap();
+    let mut trace = vec![];
+    let mut dbg = Debugger::new(prog);
+    dbg.set_trace(|e| trace.push(e.to_string()));
+    dbg.add_breakpoint(10);
+    dbg.add_watchpoint(17);
+
+    match dbg.run().unwrap() {
+        Stop::Pause(PauseCause::Input(cell)) => *cell = 3,
+        _ => panic!("Expected input"),
+    }
+    assert!(matches!(
+        dbg.run().unwrap(),
+        Stop::Watchpoint {
+            ip: 2,
+            addr: 17,
+            old: 0,
+            new: 3
+        }
+    ));
+    assert!(matches!(dbg.run().unwrap(), Stop::Breakpoint(10)));
+    assert!(matches!(dbg.step().unwrap(), Stop::Stepped));
+    dbg.remove_breakpoint(10);
+    dbg.remove_watchpoint(17);
+    assert!(matches!(
+        dbg.run().unwrap(),
+        Stop::Pause(PauseCause::Output(6))
+    ));
+    assert!(matches!(dbg.run().unwrap(), Stop::Pause(PauseCause::Halt)));
+    drop(dbg);
+    assert_eq!(trace.len(), 12);
+    assert_eq!(trace[1], "    2: add [17], [16], [17]        ; 0, 3, 17");
+    assert_eq!(trace[3], "   10: jnz [16], #2                ; 2, 2");
+
+    // The relative address of the output overflows.
+    use super::ErrorKind;
+    let mut trace = vec![];
+    let mut dbg = Debugger::new(Intcode::load_program("109,1,204,9223372036854775807,99"));
+    dbg.set_trace(|e| trace.push(e.to_string()));
+    assert!(matches!(dbg.step().unwrap(), Stop::Stepped));
+    let e = dbg.step().err().unwrap();
+    assert_eq!((e.ip, e.kind), (2, ErrorKind::AddressOverflow));
+    drop(dbg);
+    assert_eq!(
+        trace[1],
+        "    2: out rb+9223372036854775807  ; 9223372036854775807"
+    );
+}
@@ -0,0 +1,403 @@
+//! Disassembler for Intcode memory images.
+//!
+//! Code is separated from data by following the control flow from address 0. Jumps with
+//! immediate targets are followed, and so are code pointers loaded with the move idiom
+//! `add #ptr, #0, dst`, which is how compiled Intcode programs push return addresses.
+//! Everything that isn't reached is listed as `.data`.
+//!
+//! Parameters are rendered as `[addr]` for position mode, `#imm` for immediate mode and
+//! `rb+off` for relative mode. Jump targets get a label `L<addr>`.
+
+use super::{encode_opcode, Intcode, MemCell, Mode, Op, OpCode};
+
+use itertools::Itertools;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+
+/// Max number of values on a `.data` line.
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Param {
+    pub mode: Mode,
+    pub value: MemCell,
+    /// Label for immediate parameters that point into the code.
+    pub label: Option<String>,
+}
+
+impl Display for Param {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (self.mode, &self.label) {
+            (Mode::Imm, Some(label)) => write!(f, "#{}", label),
+            (Mode::Imm, None) => write!(f, "#{}", self.value),
+            (Mode::Pos, _) => write!(f, "[{}]", self.value),
+            (Mode::Rel, _) if self.value < 0 => write!(f, "rb{}", self.value),
+            (Mode::Rel, _) => write!(f, "rb+{}", self.value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction {
+    /// The raw op code cell, including the parameter modes.
+    pub opcode: MemCell,
+    pub op: Op,
+    pub params: Vec<Param>,
+}
+
+impl Instruction {
+    /// Decodes the instruction at `addr`. Returns `None` if the cell isn't a valid op code
+    /// or the parameters don't fit in memory. Op codes with mode digits for parameters the
+    /// instruction doesn't have are also rejected, since they can't be listed exactly.
+    pub fn decode(mem: &[MemCell], addr: usize) -> Option<Self> {
+        let instr = Self::decode_lenient(mem, addr)?;
+        let modes: Vec<_> = instr.params.iter().map(|p| p.mode).collect();
+        match encode_opcode(instr.opcode % 100, &modes) == instr.opcode {
+            true => Some(instr),
+            false => None,
+        }
+    }
+
+    /// Decodes the instruction at `addr` the same way as the VM does, ignoring any extra
+    /// mode digits in the op code.
+    pub(super) fn decode_lenient(mem: &[MemCell], addr: usize) -> Option<Self> {
+        let opcode = *mem.get(addr)?;
+        let op = OpCode::try_from(opcode).ok()?;
+        let modes = op.modes();
+        let values = mem.get(addr + 1..addr + 1 + modes.len())?;
+        Some(Self {
+            opcode,
+            op: op.op(),
+            params: modes
+                .iter()
+                .zip(values)
+                .map(|(&mode, &value)| Param {
+                    mode,
+                    value,
+                    label: None,
+                })
+                .collect(),
+        })
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        self.op.mnemonic()
+    }
+
+    /// The number of memory cells used by the instruction.
+    pub fn len(&self) -> usize {
+        self.params.len() + 1
+    }
+
+    /// Index of the parameter that the instruction writes to, if any.
+    pub fn write_param(&self) -> Option<usize> {
+        match self.op {
+            Op::Add | Op::Mul | Op::Lt | Op::Eq => Some(2),
+            Op::In => Some(0),
+            _ => None,
+        }
+    }
+
+    fn imm(&self, n: usize) -> Option<MemCell> {
+        let p = &self.params[n];
+        (p.mode == Mode::Imm).then_some(p.value)
+    }
+
+    /// Returns the statically known jump target, if any, and whether execution can fall
+    /// through to the next instruction.
+    fn flow(&self) -> (Option<MemCell>, bool) {
+        match self.op {
+            Op::Hlt => (None, false),
+            Op::Jnz | Op::Jz => {
+                let jump_on_zero = self.op == Op::Jz;
+                match self.imm(0) {
+                    // The condition is constant, so only one of the branches is taken.
+                    Some(c) if (c == 0) == jump_on_zero => (self.imm(1), false),
+                    Some(_) => (None, true),
+                    None => (self.imm(1), true),
+                }
+            }
+            _ => (None, true),
+        }
+    }
+
+    /// Returns the code pointer loaded by `add #ptr, #0, dst`, if this is one.
+    fn loaded_pointer(&self) -> Option<MemCell> {
+        if self.op != Op::Add {
+            return None;
+        }
+        match (self.imm(0), self.imm(1)) {
+            (Some(ptr), Some(0)) | (Some(0), Some(ptr)) => Some(ptr),
+            _ => None,
+        }
+    }
+
+    fn code_param(&self) -> Option<usize> {
+        match self.op {
+            Op::Jnz | Op::Jz => Some(1),
+            Op::Add => Some(if self.imm(1) == Some(0) { 0 } else { 1 }),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.mnemonic())?;
+        if !self.params.is_empty() {
+            write!(f, " {}", self.params.iter().join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ItemKind {
+    Instr(Instruction),
+    Data(Vec<MemCell>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Item {
+    pub addr: usize,
+    pub label: Option<String>,
+    pub kind: ItemKind,
+}
+
+impl Item {
+    /// The number of memory cells covered by the item.
+    pub fn len(&self) -> usize {
+        match &self.kind {
+            ItemKind::Instr(instr) => instr.len(),
+            ItemKind::Data(values) => values.len(),
+        }
+    }
+}
+
+impl Display for Item {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let label = self
+            .label
+            .as_ref()
+            .map(|l| format!("{}:", l))
+            .unwrap_or_default();
+        write!(f, "{:>5}: {:<8}", self.addr, label)?;
+        match &self.kind {
+            ItemKind::Instr(instr) => write!(f, "{}", instr),
+            ItemKind::Data(values) => write!(f, ".data {}", values.iter().join(", ")),
+        }
+    }
+}
+
+/// A disassembled memory image, with one item per instruction or line of data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Listing {
+    pub items: Vec<Item>,
+}
+
+impl Display for Listing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for item in &self.items {
+            writeln!(f, "{}", item)?;
+        }
+        Ok(())
+    }
+}
+
+fn label_name(addr: usize) -> String {
+    format!("L{}", addr)
+}
+
+/// Decoding state shared by the disassembly passes.
+struct Explorer<'a> {
+    mem: &'a [MemCell],
+    code: BTreeMap<usize, Instruction>,
+    claimed: Vec<bool>,
+    labels: BTreeSet<usize>,
+}
+
+impl<'a> Explorer<'a> {
+    fn decode_unclaimed(&self, addr: usize) -> Option<Instruction> {
+        let instr = Instruction::decode(self.mem, addr)?;
+        match self.claimed[addr..addr + instr.len()].iter().any(|&c| c) {
+            true => None,
+            false => Some(instr),
+        }
+    }
+
+    /// Follows the control flow from `root`.
+    fn explore(&mut self, root: usize) {
+        let mut work = vec![root];
+        // Loaded code pointers are only guesses, so they are tried after the real jumps.
+        let mut pointers = vec![];
+        loop {
+            let (addr, is_pointer) = match (work.pop(), pointers.pop()) {
+                (Some(addr), _) => (addr, false),
+                (None, Some(addr)) => (addr, true),
+                (None, None) => break,
+            };
+            if is_pointer {
+                self.labels.insert(addr);
+            }
+            if self.code.contains_key(&addr) {
+                continue;
+            }
+            let instr = match self.decode_unclaimed(addr) {
+                Some(instr) => instr,
+                None => continue,
+            };
+            self.claimed[addr..addr + instr.len()]
+                .iter_mut()
+                .for_each(|c| *c = true);
+
+            let (target, fall_through) = instr.flow();
+            if fall_through {
+                work.push(addr + instr.len());
+            }
+            if let Some(target) = target.and_then(|t| usize::try_from(t).ok()) {
+                self.labels.insert(target);
+                work.push(target);
+            }
+            if let Some(ptr) = instr.loaded_pointer().and_then(|p| usize::try_from(p).ok()) {
+                if ptr < self.mem.len() {
+                    pointers.push(ptr);
+                }
+            }
+            self.code.insert(addr, instr);
+        }
+    }
+
+    /// Checks if `addr` looks like the start of code that is only reached by indirect
+    /// jumps: valid instructions that run into known code, or at least two that end in a
+    /// halt or unconditional jump. A lone unconditional jump to valid code also counts.
+    fn looks_like_code(&self, mut addr: usize) -> bool {
+        let mut count = 0;
+        loop {
+            if addr >= self.mem.len() {
+                return false;
+            }
+            if self.code.contains_key(&addr) {
+                return count >= 1;
+            }
+            let instr = match self.decode_unclaimed(addr) {
+                Some(instr) => instr,
+                None => return false,
+            };
+            count += 1;
+            if let (target, false) = instr.flow() {
+                let target_ok = target
+                    .and_then(|t| usize::try_from(t).ok())
+                    .is_some_and(|t| Instruction::decode(self.mem, t).is_some());
+                return count >= 2 || target_ok;
+            }
+            addr += instr.len();
+        }
+    }
+}
+
+/// Disassembles a memory image, see the module documentation for the heuristics used.
+pub fn disassemble(mem: &[MemCell]) -> Listing {
+    let mut explorer = Explorer {
+        mem,
+        code: BTreeMap::new(),
+        claimed: vec![false; mem.len()],
+        labels: BTreeSet::new(),
+    };
+    explorer.explore(0);
+    let mut addr = 0;
+    while addr < mem.len() {
+        if !explorer.claimed[addr] && explorer.looks_like_code(addr) {
+            explorer.labels.insert(addr);
+            explorer.explore(addr);
+        }
+        addr += 1;
+    }
+    let Explorer {
+        code,
+        claimed,
+        mut labels,
+        ..
+    } = explorer;
+    let mut code = code;
+
+    // Only keep labels for targets that turned out to be code.
+    labels.retain(|addr| code.contains_key(addr));
+
+    for instr in code.values_mut() {
+        if let Some(n) = instr.code_param() {
+            let p = &mut instr.params[n];
+            if p.mode == Mode::Imm && p.value >= 0 && labels.contains(&(p.value as usize)) {
+                p.label = Some(label_name(p.value as usize));
+            }
+        }
+    }
+
+    let mut items = vec![];
+    let mut addr = 0;
+    while addr < mem.len() {
+        let label = labels.contains(&addr).then(|| label_name(addr));
+        let kind = if let Some(instr) = code.remove(&addr) {
+            ItemKind::Instr(instr)
+        } else {
+            let len = (addr..mem.len())
+                .take(DATA_PER_LINE)
+                .take_while(|a| !claimed[*a])
+                .count();
+            ItemKind::Data(mem[addr..addr + len].to_vec())
+        };
+        let item = Item { addr, label, kind };
+        addr += item.len();
+        items.push(item);
+    }
+    Listing { items }
+}
+
+impl Intcode {
+    pub fn disassemble(&self) -> Listing {
+        disassemble(&self.memory().to_vec())
+    }
+
+    /// Decodes the instruction at `ip` the same way as the VM will execute it.
+    pub(super) fn decode_next(&self) -> Option<Instruction> {
+        let window: Vec<_> = self.mem.cells(self.ip..self.ip + 4).collect();
+        Instruction::decode_lenient(&window, 0)
+    }
+
+    /// The address that a parameter of the next instruction refers to. Returns `None` for
+    /// immediate parameters and for negative or overflowing addresses.
+    pub(super) fn param_addr(&self, param: &Param) -> Option<usize> {
+        let addr = match param.mode {
+            Mode::Imm => return None,
+            Mode::Pos => param.value,
+            Mode::Rel => self.rel_base.checked_add(param.value)?,
+        };
+        usize::try_from(addr).ok()
+    }
+}
+
+#[test]
+fn listing() {
+    let prog = Intcode::load_program("3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9");
+    assert_eq!(
+        prog.disassemble().to_string(),
+        "    0:         in [12]
+    2:         jz [12], [15]
+    5:         add [13], [14], [13]
+    9:         out [13]
+   11:         hlt
+   12:         .data -1, 0, 1, 9
+"
+    );
+    let prog = Intcode::load_program("1105,1,7,99,1,2,3,21101,3,0,-2,1106,0,3");
+    assert_eq!(
+        prog.disassemble().to_string(),
+        "    0:         jnz #1, #L7
+    3: L3:     hlt
+    4:         .data 1, 2, 3
+    7: L7:     add #L3, #0, rb-2
+   11:         jz #0, #L3
+"
+    );
+}
@@ -0,0 +1,322 @@
+//! Paged copy-on-write memory for the Intcode VM.
+//!
+//! Memory is split into fixed size pages that are shared between clones of a machine until
+//! one of them writes to the page, so cloning a fresh machine only copies the page table.
+//! Pages that haven't been written to all share the same zero page. A page that has been
+//! written to is owned by its memory, so further writes don't check for sharing, and it is
+//! copied when the memory is cloned, like a flat `Vec` would be.
+//!
+//! The low addresses, where the program image lives, use a page table indexed by page
+//! number. Pages above `DENSE_CELLS` are kept in a map and only allocated when written to,
+//! so programs can use any address without allocating the memory in between.
+//!
+//! The ignored `flat_vs_paged` test compares indexing against a flat `Vec`. The page table
+//! costs a pointer hop on each access: on 2019 day 9 part 2 the VM took about 20% longer
+//! than with a flat `Vec`, and about 45% longer when every write went through
+//! `Arc::make_mut`.
+
+use super::cell::Cell;
+use super::MemCell;
+
+use itertools::{EitherOrBoth, Itertools};
+
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::ops::{Index, IndexMut, Range};
+use std::sync::Arc;
+
+const PAGE_BITS: usize = 10;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+/// Number of cells in the dense tier.
+const DENSE_CELLS: usize = 1 << 20;
+const DENSE_PAGES: usize = DENSE_CELLS >> PAGE_BITS;
+
+type Cells<C> = [C; PAGE_SIZE];
+
+#[derive(Clone, Debug)]
+enum Page<C> {
+    Shared(Arc<Cells<C>>),
+    Owned(Box<Cells<C>>),
+}
+
+impl<C: Cell> Page<C> {
+    #[inline(always)]
+    fn cells(&self) -> &Cells<C> {
+        match self {
+            Page::Shared(cells) => cells,
+            Page::Owned(cells) => cells,
+        }
+    }
+
+    /// Takes ownership of the page, copying it if it is shared.
+    #[inline(always)]
+    fn cells_mut(&mut self) -> &mut Cells<C> {
+        if let Page::Shared(_) = self {
+            self.make_owned();
+        }
+        match self {
+            Page::Owned(cells) => cells,
+            Page::Shared(_) => unreachable!(),
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn make_owned(&mut self) {
+        if let Page::Shared(cells) = self {
+            *self = Page::Owned(Box::new(cells.as_ref().clone()));
+        }
+    }
+
+    /// Whether both are the same shared page. Owned pages are never the same.
+    fn same(&self, other: &Self) -> bool {
+        matches!((self, other), (Page::Shared(a), Page::Shared(b)) if Arc::ptr_eq(a, b))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Memory<C = MemCell> {
+    pages: Vec<Page<C>>,
+    /// Pages above the dense tier, by page number.
+    sparse: BTreeMap<usize, Page<C>>,
+    zero: Page<C>,
+    /// End of the program image or the highest write in the dense tier.
+    len: usize,
+}
+
+impl<C: Cell> Memory<C> {
+    /// Creates a memory holding `cells`, with the pages shared until written to.
+    pub fn new(cells: Vec<C>) -> Self {
+        let zero = Arc::new(std::array::from_fn(|_| C::default()));
+        let mut mem = Self {
+            pages: vec![],
+            sparse: BTreeMap::new(),
+            zero: Page::Shared(zero.clone()),
+            len: cells.len().min(DENSE_CELLS),
+        };
+        for (n, chunk) in cells.into_iter().chunks(PAGE_SIZE).into_iter().enumerate() {
+            let mut cells = zero.as_ref().clone();
+            for (cell, val) in cells.iter_mut().zip(chunk) {
+                *cell = val;
+            }
+            let page = Page::Shared(Arc::new(cells));
+            if n < DENSE_PAGES {
+                mem.pages.push(page);
+            } else {
+                mem.sparse.insert(n, page);
+            }
+        }
+        mem
+    }
+
+    /// The size of the program image, extended by any writes past its end that are in the
+    /// dense tier. All other cells read as zero.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, addr: usize) -> C {
+        self[addr].clone()
+    }
+
+    pub fn cells(&self, range: Range<usize>) -> impl Iterator<Item = C> + '_ {
+        range.map(move |addr| self[addr].clone())
+    }
+
+    /// The first `len()` cells.
+    pub fn to_vec(&self) -> Vec<C> {
+        self.cells(0..self.len).collect()
+    }
+
+    fn all_pages(&self) -> impl Iterator<Item = (usize, &Page<C>)> {
+        self.pages
+            .iter()
+            .enumerate()
+            .chain(self.sparse.iter().map(|(&n, p)| (n, p)))
+    }
+
+    /// The number of pages that have been written to or hold the program image.
+    pub fn allocated_pages(&self) -> usize {
+        self.all_pages()
+            .filter(|(_, page)| !page.same(&self.zero))
+            .count()
+    }
+
+    /// The number of allocated pages that aren't shared with `other`.
+    pub fn unshared_pages(&self, other: &Self) -> usize {
+        self.all_pages()
+            .filter(|(_, page)| !page.same(&self.zero))
+            .filter(|&(n, page)| !other.page(n).is_some_and(|o| page.same(o)))
+            .count()
+    }
+
+    #[inline(always)]
+    fn page(&self, n: usize) -> Option<&Page<C>> {
+        match n < DENSE_PAGES {
+            true => self.pages.get(n),
+            false => self.sparse.get(&n),
+        }
+    }
+
+    /// Pages with a non-zero cell, which are the only ones that matter for comparisons.
+    fn used_pages(&self) -> impl Iterator<Item = (usize, &Page<C>)> {
+        self.all_pages()
+            .filter(move |(_, page)| !page.same(&self.zero) && !page.cells().iter().all(C::is_zero))
+    }
+}
+
+impl<C: Cell> Index<usize> for Memory<C> {
+    type Output = C;
+
+    #[inline(always)]
+    fn index(&self, addr: usize) -> &C {
+        match self.pages.get(addr >> PAGE_BITS) {
+            Some(page) => &page.cells()[addr % PAGE_SIZE],
+            None => self.index_outside(addr),
+        }
+    }
+}
+
+impl<C: Cell> IndexMut<usize> for Memory<C> {
+    #[inline(always)]
+    fn index_mut(&mut self, addr: usize) -> &mut C {
+        // The page table covers `len`, so only writes past it need to look further.
+        let page = match addr < self.len {
+            true => &mut self.pages[addr >> PAGE_BITS],
+            false => self.page_outside(addr),
+        };
+        &mut page.cells_mut()[addr % PAGE_SIZE]
+    }
+}
+
+/// The slow paths of indexing, for addresses past the page table or `len()`.
+impl<C: Cell> Memory<C> {
+    #[cold]
+    #[inline(never)]
+    fn index_outside(&self, addr: usize) -> &C {
+        match self.sparse.get(&(addr >> PAGE_BITS)) {
+            Some(page) => &page.cells()[addr % PAGE_SIZE],
+            None => &self.zero.cells()[0],
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn page_outside(&mut self, addr: usize) -> &mut Page<C> {
+        let n = addr >> PAGE_BITS;
+        if n < DENSE_PAGES {
+            if n >= self.pages.len() {
+                self.pages.resize(n + 1, self.zero.clone());
+            }
+            self.len = self.len.max(addr + 1);
+            &mut self.pages[n]
+        } else {
+            let zero = &self.zero;
+            self.sparse.entry(n).or_insert_with(|| zero.clone())
+        }
+    }
+}
+
+/// Memories are equal if all their cells are, regardless of where they have been
+/// written to.
+impl<C: Cell> PartialEq for Memory<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.used_pages()
+            .zip_longest(other.used_pages())
+            .all(|pages| match pages {
+                EitherOrBoth::Both((n, a), (m, b)) => {
+                    n == m && (a.same(b) || a.cells() == b.cells())
+                }
+                _ => false,
+            })
+    }
+}
+
+impl<C: Cell> Eq for Memory<C> {}
+
+impl<C: Cell> Hash for Memory<C> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for (n, page) in self.used_pages() {
+            n.hash(state);
+            page.cells()[..].hash(state);
+        }
+    }
+}
+
+#[test]
+fn copy_on_write() {
+    let mut a: Memory = Memory::new((0..3000).collect());
+    assert_eq!((a.len(), a[2999], a[3000]), (3000, 2999, 0));
+    let mut b = a.clone();
+    assert_eq!(b.unshared_pages(&a), 0);
+    b[5] = -1;
+    assert_eq!((a[5], b[5], b.unshared_pages(&a)), (5, -1, 1));
+    assert_ne!(a, b);
+
+    b[100_000] = 1;
+    assert_eq!((b.len(), b.unshared_pages(&a)), (100_001, 2));
+    b[5] = 5;
+    b[100_000] = 0;
+    assert_eq!(a, b);
+    a[2999] = 0;
+    assert_eq!(
+        a.to_vec()[2990..],
+        [2990, 2991, 2992, 2993, 2994, 2995, 2996, 2997, 2998, 0]
+    );
+    assert_ne!(a, b);
+}
+
+#[test]
+fn sparse() {
+    use super::Intcode;
+
+    let far = 1 << 50;
+    let prog = format!("1101,5,6,{0},4,{0},4,{1},99", far, far + 1);
+    let mut vm = Intcode::load_program(&prog);
+    assert_eq!(vm.run_until_end(&[]).unwrap(), [11, 0]);
+    assert_eq!((vm.memory().len(), vm.memory().allocated_pages()), (9, 2));
+    assert_ne!(vm.memory(), Intcode::load_program(&prog).memory());
+    vm.poke(far, 0);
+    assert_eq!(vm.memory(), Intcode::load_program(&prog).memory());
+}
+
+/// Run time of paged memory against a flat `Vec`, with the reads and writes of an
+/// arithmetic instruction on a program sized image. Checking for sharing on each write
+/// made this about 7 times slower. Ignored, as timing depends on the machine, run it with
+/// `cargo test --release flat_vs_paged -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn flat_vs_paged() {
+    use std::time::Instant;
+
+    const CELLS: usize = 4096;
+    fn run<M: IndexMut<usize, Output = MemCell>>(mem: &mut M) -> MemCell {
+        for round in 0..1000 {
+            for ip in (0..CELLS - 3).step_by(4) {
+                let (a, b) = (mem[ip + 1] as usize % CELLS, mem[ip + 2] as usize % CELLS);
+                mem[(ip + round) % CELLS] = mem[a].wrapping_add(mem[b]);
+            }
+        }
+        mem[0]
+    }
+    let time = |run: &mut dyn FnMut() -> MemCell| {
+        (0..5)
+            .map(|_| {
+                let start = Instant::now();
+                std::hint::black_box(run());
+                start.elapsed()
+            })
+            .min()
+            .unwrap()
+    };
+    let image: Vec<MemCell> = (0..CELLS as MemCell).collect();
+    let flat = time(&mut || run(&mut image.clone()));
+    let paged = time(&mut || run(&mut Memory::new(image.clone())));
+    let ratio = paged.as_secs_f64() / flat.as_secs_f64().max(1e-9);
+    println!("flat {:?}, paged {:?}, {:.2}x", flat, paged, ratio);
+    assert!(ratio < 4.0, "Paged memory is much slower than a flat Vec");
+}
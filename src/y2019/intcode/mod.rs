@@ -0,0 +1,660 @@
+use std::collections::VecDeque;
+use std::convert::{TryFrom, TryInto};
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+
+pub mod ascii;
+pub mod asm;
+pub mod cache;
+pub mod cell;
+pub mod debug;
+pub mod disasm;
+pub mod memory;
+pub mod network;
+pub mod profile;
+pub mod robot;
+
+use cell::Cell;
+use memory::Memory;
+
+/// The kind of an instruction, without its parameter modes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Op {
+    Add,
+    Mul,
+    In,
+    Out,
+    Jnz,
+    Jz,
+    Lt,
+    Eq,
+    Arb,
+    Hlt,
+}
+
+/// Base op code, mnemonic and number of parameters of each instruction, in the order of
+/// `Op`. Everything that needs these goes through this table.
+const OPS: [(Op, MemCell, &str, usize); 10] = [
+    (Op::Add, 1, "add", 3),
+    (Op::Mul, 2, "mul", 3),
+    (Op::In, 3, "in", 1),
+    (Op::Out, 4, "out", 1),
+    (Op::Jnz, 5, "jnz", 2),
+    (Op::Jz, 6, "jz", 2),
+    (Op::Lt, 7, "lt", 3),
+    (Op::Eq, 8, "eq", 3),
+    (Op::Arb, 9, "arb", 1),
+    (Op::Hlt, 99, "hlt", 0),
+];
+
+/// `OPS` indexed by base op code.
+const OPS_BY_CODE: [Option<Op>; 100] = {
+    let mut ops = [None; 100];
+    let mut n = 0;
+    while n < OPS.len() {
+        ops[OPS[n].1 as usize] = Some(OPS[n].0);
+        n += 1;
+    }
+    ops
+};
+
+impl Op {
+    pub fn code(self) -> MemCell {
+        OPS[self as usize].1
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        OPS[self as usize].2
+    }
+
+    /// The number of parameters.
+    pub fn arity(self) -> usize {
+        OPS[self as usize].3
+    }
+
+    /// The instruction for a base op code, without the mode digits.
+    pub fn from_code(code: MemCell) -> Option<Op> {
+        usize::try_from(code)
+            .ok()
+            .and_then(|code| OPS_BY_CODE.get(code).copied().flatten())
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Op> {
+        OPS.iter()
+            .find(|(_, _, m, _)| *m == mnemonic)
+            .map(|&(op, _, _, _)| op)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum OpCode {
+    Add(OpMode<3>),
+    Mul(OpMode<3>),
+    Input(OpMode<1>),
+    Output(OpMode<1>),
+    JNZ(OpMode<2>),
+    JZ(OpMode<2>),
+    Less(OpMode<3>),
+    Eq(OpMode<3>),
+    AdjRelBase(OpMode<1>),
+    Halt(OpMode<0>),
+}
+
+impl TryFrom<MemCell> for OpCode {
+    type Error = ErrorKind;
+
+    fn try_from(value: MemCell) -> Result<Self, Self::Error> {
+        let op = Op::from_code(value % 100).ok_or(ErrorKind::UnknownOpcode)?;
+        use OpCode::*;
+        Ok(match op {
+            Op::Add => Add(value.try_into()?),
+            Op::Mul => Mul(value.try_into()?),
+            Op::In => Input(value.try_into()?),
+            Op::Out => Output(value.try_into()?),
+            Op::Jnz => JNZ(value.try_into()?),
+            Op::Jz => JZ(value.try_into()?),
+            Op::Lt => Less(value.try_into()?),
+            Op::Eq => Eq(value.try_into()?),
+            Op::Arb => AdjRelBase(value.try_into()?),
+            Op::Hlt => Halt(value.try_into()?),
+        })
+    }
+}
+
+impl OpCode {
+    fn op(&self) -> Op {
+        use OpCode::*;
+        match self {
+            Add(_) => Op::Add,
+            Mul(_) => Op::Mul,
+            Input(_) => Op::In,
+            Output(_) => Op::Out,
+            JNZ(_) => Op::Jnz,
+            JZ(_) => Op::Jz,
+            Less(_) => Op::Lt,
+            Eq(_) => Op::Eq,
+            AdjRelBase(_) => Op::Arb,
+            Halt(_) => Op::Hlt,
+        }
+    }
+
+    fn modes(&self) -> &[Mode] {
+        use OpCode::*;
+        match self {
+            Add(m) | Mul(m) | Less(m) | Eq(m) => &m.0,
+            Input(m) | Output(m) | AdjRelBase(m) => &m.0,
+            JNZ(m) | JZ(m) => &m.0,
+            Halt(m) => &m.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct OpMode<const N: usize>([Mode; N]);
+
+impl<const N: usize> TryFrom<MemCell> for OpMode<N> {
+    type Error = ErrorKind;
+
+    fn try_from(val: MemCell) -> Result<Self, Self::Error> {
+        let mut m = [Mode::Pos; N];
+        let mut c = val / 100; // remove op code
+        for mode in m.iter_mut() {
+            *mode = match c % 10 {
+                0 => Mode::Pos,
+                1 => Mode::Imm,
+                2 => Mode::Rel,
+                d => return Err(ErrorKind::BadMode(d)),
+            };
+            c /= 10;
+        }
+        Ok(Self(m))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mode {
+    Imm,
+    Pos,
+    Rel,
+}
+
+impl Mode {
+    /// The digit used for the mode in an op code.
+    fn digit(self) -> MemCell {
+        match self {
+            Mode::Pos => 0,
+            Mode::Imm => 1,
+            Mode::Rel => 2,
+        }
+    }
+}
+
+/// Builds the op code cell for the given base op code and parameter modes.
+fn encode_opcode(op: MemCell, modes: &[Mode]) -> MemCell {
+    let mut mult = 100;
+    modes.iter().fold(op, |code, m| {
+        let code = code + m.digit() * mult;
+        mult *= 10;
+        code
+    })
+}
+
+pub type MemCell = isize;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+    UnknownOpcode,
+    /// Invalid parameter mode digit.
+    BadMode(MemCell),
+    NegativeAddress(MemCell),
+    /// Address or relative base too large for the address space.
+    AddressOverflow,
+    /// Result of an addition or multiplication doesn't fit in the cell type, or an output
+    /// value is out of range for a robot harness command.
+    Overflow,
+    /// Output parameter in immediate mode.
+    ImmediateWrite,
+    /// The program asked for more input than was given to `run_until_end()`. The VM is
+    /// left at the input instruction, so it can be resumed with more input.
+    InputExhausted,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct IntcodeError<C = MemCell> {
+    /// Address of the failing instruction.
+    pub ip: usize,
+    pub opcode: C,
+    pub kind: ErrorKind,
+}
+
+impl<C: Display> Display for IntcodeError<C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Intcode error at ip {} (op code {}): ",
+            self.ip, self.opcode
+        )?;
+        match self.kind {
+            ErrorKind::UnknownOpcode => write!(f, "unknown op code"),
+            ErrorKind::BadMode(m) => write!(f, "bad parameter mode {}", m),
+            ErrorKind::NegativeAddress(a) => write!(f, "negative address {}", a),
+            ErrorKind::AddressOverflow => write!(f, "address overflow"),
+            ErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            ErrorKind::ImmediateWrite => write!(f, "write to immediate mode parameter"),
+            ErrorKind::InputExhausted => write!(f, "out of input"),
+        }
+    }
+}
+
+impl<C: Cell> std::error::Error for IntcodeError<C> {}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Intcode<C: Cell = MemCell> {
+    mem: Memory<C>,
+    ip: usize,
+    rel_base: isize,
+    /// Queued input, consumed by input instructions before pausing for more.
+    input: VecDeque<C>,
+}
+
+pub enum PauseCause<'a, C = MemCell> {
+    Halt,
+    Input(&'a mut C),
+    Output(C),
+}
+
+/// Result of executing one instruction, with the input cell as an address so that the
+/// execution loop doesn't hold a borrow of the memory.
+enum Event<C = MemCell> {
+    Continue,
+    Halt,
+    Input(usize),
+    Output(C),
+}
+
+impl Intcode {
+    pub fn new(mem: Vec<MemCell>) -> Self {
+        Self::from_cells(mem)
+    }
+
+    pub fn load_program(prog: &str) -> Self {
+        Self::load_cells(prog)
+    }
+}
+
+impl<C: Cell> Intcode<C> {
+    /// Same as `new()`, for any cell type.
+    pub fn from_cells(mem: Vec<C>) -> Self {
+        Self {
+            mem: Memory::new(mem),
+            ip: 0,
+            rel_base: 0,
+            input: VecDeque::new(),
+        }
+    }
+
+    /// Same as `load_program()`, for any cell type.
+    pub fn load_cells(prog: &str) -> Self
+    where
+        C::Err: Debug,
+    {
+        Self::from_cells(prog.split(',').map(|n| n.parse().unwrap()).collect())
+    }
+
+    /// Resolves the addresses of the parameters of the instruction at `ip`.
+    fn op_addr<const N: usize>(&self, modes: OpMode<N>) -> Result<[usize; N], ErrorKind> {
+        let mut r = [0; N];
+        for (n, (r, mode)) in r.iter_mut().zip(modes.0).enumerate() {
+            let imm_addr = self.ip + n + 1;
+            let addr = match mode {
+                Mode::Imm => Some(imm_addr as isize),
+                Mode::Pos => self.mem[imm_addr].to_isize(),
+                Mode::Rel => self.mem[imm_addr]
+                    .to_isize()
+                    .and_then(|offset| self.rel_base.checked_add(offset)),
+            }
+            .ok_or(ErrorKind::AddressOverflow)?;
+            *r = usize::try_from(addr).map_err(|_| ErrorKind::NegativeAddress(addr))?;
+        }
+        Ok(r)
+    }
+
+    fn op_addr_and_ip<const N: usize>(
+        &mut self,
+        modes: OpMode<N>,
+    ) -> Result<[usize; N], ErrorKind> {
+        let r = self.op_addr(modes)?;
+        self.ip += N + 1;
+        Ok(r)
+    }
+
+    pub fn run_until_end(&mut self, input: &[C]) -> Result<Vec<C>, IntcodeError<C>> {
+        self.run_to_halt(input, Self::exec)
+    }
+
+    /// Queues `input` and runs the program until it halts, using `exec` to execute each
+    /// instruction.
+    fn run_to_halt<F>(&mut self, input: &[C], mut exec: F) -> Result<Vec<C>, IntcodeError<C>>
+    where
+        F: FnMut(&mut Self) -> Result<Event<C>, IntcodeError<C>>,
+    {
+        self.input.extend(input.iter().cloned());
+        let mut output = vec![];
+        loop {
+            let ip = self.ip;
+            match exec(self)? {
+                Event::Continue => {}
+                Event::Halt => return Ok(output),
+                Event::Input(_) => {
+                    self.ip = ip;
+                    return Err(IntcodeError {
+                        ip,
+                        opcode: self.mem.get(ip),
+                        kind: ErrorKind::InputExhausted,
+                    });
+                }
+                Event::Output(out) => output.push(out),
+            }
+        }
+    }
+
+    pub fn run(&mut self) -> Result<PauseCause<'_, C>, IntcodeError<C>> {
+        let event = loop {
+            match self.exec()? {
+                Event::Continue => {}
+                event => break event,
+            }
+        };
+        Ok(self.pause(event).unwrap())
+    }
+
+    /// Executes a single instruction. Returns the pause cause if the instruction was an
+    /// input, output or halt.
+    pub fn step(&mut self) -> Result<Option<PauseCause<'_, C>>, IntcodeError<C>> {
+        let event = self.exec()?;
+        Ok(self.pause(event))
+    }
+
+    fn pause(&mut self, event: Event<C>) -> Option<PauseCause<'_, C>> {
+        match event {
+            Event::Continue => None,
+            Event::Halt => Some(PauseCause::Halt),
+            Event::Input(addr) => Some(PauseCause::Input(&mut self.mem[addr])),
+            Event::Output(val) => Some(PauseCause::Output(val)),
+        }
+    }
+
+    /// Executes the instruction at `ip`.
+    #[inline(always)]
+    fn exec(&mut self) -> Result<Event<C>, IntcodeError<C>> {
+        let ip = self.ip;
+        let opcode = self.mem.get(ip);
+        let op = opcode
+            .to_isize()
+            .ok_or(ErrorKind::UnknownOpcode)
+            .and_then(OpCode::try_from);
+        match op {
+            Ok(op) => self.exec_op(op, opcode),
+            Err(kind) => Err(IntcodeError { ip, opcode, kind }),
+        }
+    }
+
+    /// Executes the instruction at `ip`, which has already been decoded to `op`.
+    #[inline(always)]
+    fn exec_op(&mut self, op: OpCode, opcode: C) -> Result<Event<C>, IntcodeError<C>> {
+        let ip = self.ip;
+        let error = |kind| IntcodeError {
+            ip,
+            opcode: opcode.clone(),
+            kind,
+        };
+        macro_rules! aritm3 {
+            ($mode:ident, |$a:ident, $b:ident| $f:expr) => {{
+                if $mode.0[2] == Mode::Imm {
+                    return Err(error(ErrorKind::ImmediateWrite));
+                }
+                let [a, b, c] = self.op_addr_and_ip($mode).map_err(error)?;
+                let ($a, $b) = (&self.mem[a], &self.mem[b]);
+                self.mem[c] = $f.ok_or_else(|| error(ErrorKind::Overflow))?;
+            }};
+        }
+        match op {
+            OpCode::Add(mode) => aritm3!(mode, |a, b| a.checked_add(b)),
+            OpCode::Mul(mode) => aritm3!(mode, |a, b| a.checked_mul(b)),
+            OpCode::Input(mode) => {
+                if mode.0[0] == Mode::Imm {
+                    return Err(error(ErrorKind::ImmediateWrite));
+                }
+                let [target] = self.op_addr_and_ip(mode).map_err(error)?;
+                match self.input.pop_front() {
+                    Some(val) => self.mem[target] = val,
+                    None => return Ok(Event::Input(target)),
+                }
+            }
+            OpCode::Output(mode) => {
+                let [target] = self.op_addr_and_ip(mode).map_err(error)?;
+                return Ok(Event::Output(self.mem.get(target)));
+            }
+            OpCode::JNZ(mode) => {
+                let [c, t] = self.op_addr_and_ip(mode).map_err(error)?;
+                if !self.mem[c].is_zero() {
+                    self.jump(&self.mem.get(t)).map_err(error)?;
+                }
+            }
+            OpCode::JZ(mode) => {
+                let [c, t] = self.op_addr_and_ip(mode).map_err(error)?;
+                if self.mem[c].is_zero() {
+                    self.jump(&self.mem.get(t)).map_err(error)?;
+                }
+            }
+            OpCode::Less(mode) => aritm3!(mode, |a, b| Some(C::from_bool(a < b))),
+            OpCode::Eq(mode) => aritm3!(mode, |a, b| Some(C::from_bool(a == b))),
+            OpCode::AdjRelBase(mode) => {
+                let [o] = self.op_addr_and_ip(mode).map_err(error)?;
+                self.rel_base = self.mem[o]
+                    .to_isize()
+                    .and_then(|offset| self.rel_base.checked_add(offset))
+                    .ok_or_else(|| error(ErrorKind::AddressOverflow))?;
+            }
+            OpCode::Halt(_mode) => return Ok(Event::Halt),
+        }
+        Ok(Event::Continue)
+    }
+
+    /// Queues a value for the input instructions. `run()` only pauses for input once the
+    /// queue is empty.
+    pub fn push_input(&mut self, val: C) {
+        self.input.push_back(val);
+    }
+
+    /// The number of queued input values.
+    pub fn queued_input(&self) -> usize {
+        self.input.len()
+    }
+
+    /// Checks if the next instruction is an input instruction that will pause the VM.
+    /// An input instruction that fails to execute doesn't count.
+    pub fn wants_input(&self) -> bool {
+        let op = self.mem[self.ip].to_isize().map(OpCode::try_from);
+        match op {
+            Some(Ok(OpCode::Input(mode))) => {
+                self.input.is_empty() && mode.0[0] != Mode::Imm && self.op_addr(mode).is_ok()
+            }
+            _ => false,
+        }
+    }
+
+    fn jump(&mut self, target: &C) -> Result<(), ErrorKind> {
+        let target = target.to_isize().ok_or(ErrorKind::AddressOverflow)?;
+        self.ip = usize::try_from(target).map_err(|_| ErrorKind::NegativeAddress(target))?;
+        Ok(())
+    }
+
+    /// The memory image of the program.
+    pub fn memory(&self) -> &Memory<C> {
+        &self.mem
+    }
+
+    /// Address of the next instruction.
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn rel_base(&self) -> isize {
+        self.rel_base
+    }
+
+    pub fn peek(&self, addr: usize) -> C {
+        self.mem.get(addr)
+    }
+
+    pub fn poke(&mut self, addr: usize, val: C) -> C {
+        std::mem::replace(&mut self.mem[addr], val)
+    }
+}
+
+/// A saved machine state, see `Intcode::snapshot()`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Snapshot<C: Cell = MemCell>(Intcode<C>);
+
+impl<C: Cell> Intcode<C> {
+    /// Saves the machine state. This is cheap, since the memory pages are shared until
+    /// either machine writes to them.
+    pub fn snapshot(&self) -> Snapshot<C> {
+        Snapshot(self.clone())
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot<C>) {
+        self.clone_from(&snapshot.0);
+    }
+
+    /// Hash of the full machine state, including queued input. Unlike the `Hash` impl used
+    /// by `HashSet`, this is the same between runs, so it can be stored.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = seahash::SeaHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+#[test]
+fn op_table() {
+    for (n, &(op, code, mnemonic, arity)) in OPS.iter().enumerate() {
+        assert_eq!(op as usize, n);
+        assert_eq!(Op::from_code(code), Some(op));
+        assert_eq!(Op::from_mnemonic(mnemonic), Some(op));
+        let decoded = OpCode::try_from(code).unwrap();
+        assert_eq!((decoded.op(), decoded.modes().len()), (op, arity));
+    }
+    assert_eq!(Op::from_code(0), None);
+    assert_eq!(Op::from_code(-1), None);
+    assert_eq!(Op::from_mnemonic("nop"), None);
+}
+
+#[test]
+fn errors() {
+    let err = |prog: &str, input: &[MemCell]| {
+        Intcode::load_program(prog)
+            .run_until_end(input)
+            .unwrap_err()
+    };
+    let e = err("1,0,0,0,42", &[]);
+    assert_eq!((e.ip, e.opcode, e.kind), (4, 42, ErrorKind::UnknownOpcode));
+    assert_eq!(err("301,0,0,0,99", &[]).kind, ErrorKind::BadMode(3));
+    assert_eq!(err("1,-1,0,0,99", &[]).kind, ErrorKind::NegativeAddress(-1));
+    assert_eq!(err("1105,1,-7", &[]).kind, ErrorKind::NegativeAddress(-7));
+    assert_eq!(err("11101,1,1,0,99", &[]).kind, ErrorKind::ImmediateWrite);
+    assert_eq!(err("3,0,3,0,99", &[1]).kind, ErrorKind::InputExhausted);
+
+    // Echoes the first input, then outputs the sum of both.
+    let mut vm = Intcode::load_program("3,13,4,13,3,14,1,13,14,15,4,15,99,0,0,0");
+    let e = vm.run_until_end(&[5]).unwrap_err();
+    assert_eq!((e.ip, e.opcode, e.kind), (4, 3, ErrorKind::InputExhausted));
+    assert_eq!(vm.ip(), 4);
+    assert!(vm.wants_input());
+    assert_eq!(vm.run_until_end(&[7]), Ok(vec![12]));
+    // Input instructions that can't execute don't want input, so they report their error.
+    for (prog, kind) in [
+        ("103,0,99", ErrorKind::ImmediateWrite),
+        ("303,0,99", ErrorKind::BadMode(3)),
+        ("3,-2,99", ErrorKind::NegativeAddress(-2)),
+    ] {
+        let mut vm = Intcode::load_program(prog);
+        assert!(!vm.wants_input());
+        assert_eq!(vm.read_line().unwrap_err().kind, kind);
+    }
+    let mut vm = Intcode::load_program("3,13,4,13,3,14,1,13,14,15,4,15,99,0,0,0");
+    let mut cache = cache::DecodeCache::new();
+    assert!(vm.run_until_end_cached(&[5], &mut cache).is_err());
+    assert_eq!(vm.run_until_end_cached(&[7], &mut cache), Ok(vec![12]));
+    assert_eq!(
+        err("1,0,0,0,1,-3,0,0,99", &[]).to_string(),
+        "Intcode error at ip 4 (op code 1): negative address -3"
+    );
+}
+
+#[test]
+fn snapshots() {
+    use std::collections::HashSet;
+
+    // Counts down from the input, outputting each value.
+    let mut vm = Intcode::load_program("3,12,4,12,1001,12,-1,12,1005,12,2,99,7");
+    vm.push_input(3);
+    let start = vm.snapshot();
+    assert_eq!(vm.run_until_end(&[]).unwrap(), [3, 2, 1]);
+    let end_hash = vm.state_hash();
+    vm.restore(&start);
+    assert_eq!(vm.snapshot(), start);
+
+    let mut seen = HashSet::new();
+    let mut hashes = HashSet::new();
+    while seen.insert(vm.clone()) {
+        hashes.insert(vm.state_hash());
+        if let Some(PauseCause::Halt) = vm.step().unwrap() {
+            break;
+        }
+    }
+    assert_eq!(seen.len(), hashes.len());
+    assert_eq!(vm.state_hash(), end_hash);
+    assert_ne!(vm.memory(), start.0.memory());
+}
+
+#[test]
+fn cell_types() {
+    use cell::BigCell;
+
+    let prog = format!("1102,{0},{0},7,4,7,99,0", 1u64 << 40);
+    let e = Intcode::load_program(&prog).run_until_end(&[]).unwrap_err();
+    assert_eq!((e.ip, e.kind), (0, ErrorKind::Overflow));
+    let mut vm = Intcode::<i128>::load_cells(&prog);
+    assert_eq!(vm.run_until_end(&[]).unwrap(), [1 << 80]);
+
+    let big = "1000000000000000000000000000000";
+    let mut vm = Intcode::<BigCell>::load_cells(&format!("1102,{0},{0},7,4,7,99,0", big));
+    let out = vm.run_until_end(&[]).unwrap();
+    assert_eq!(out[0].to_string(), format!("1{}", "0".repeat(60)));
+    let e = Intcode::<i128>::load_cells(&format!("1106,0,{}", big))
+        .run_until_end(&[])
+        .unwrap_err();
+    assert_eq!(e.kind, ErrorKind::AddressOverflow);
+
+    let day9 = crate::load_strings("data/2019/day9.txt").next().unwrap();
+    let expected = Intcode::load_program(&day9).run_until_end(&[1]).unwrap();
+    let wide = Intcode::<i128>::load_cells(&day9)
+        .run_until_end(&[1])
+        .unwrap();
+    let big = Intcode::<BigCell>::load_cells(&day9)
+        .run_until_end(&[BigCell::from_isize(1)])
+        .unwrap();
+    assert_eq!(
+        wide,
+        expected.iter().map(|&v| v as i128).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        big,
+        expected
+            .iter()
+            .map(|&v| BigCell::from_isize(v))
+            .collect::<Vec<_>>()
+    );
+}
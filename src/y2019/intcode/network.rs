@@ -0,0 +1,255 @@
+//! Deterministic scheduler for networks of Intcode machines, run cooperatively on one
+//! thread.
+//!
+//! The machines take turns in round-robin order. A machine runs until it blocks on input
+//! or halts, and its output is routed to the input queues of the other machines according
+//! to the `Wiring`. Values that leave the network are reported as `NetEvent`s.
+
+use super::{Intcode, IntcodeError, MemCell, PauseCause};
+
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Wiring {
+    /// Machine n sends its output to machine n + 1. Output of the last machine leaves the
+    /// network.
+    Chain,
+    /// Like `Chain`, but the output of the last machine is also fed back to the first.
+    Loop,
+    /// Machines send packets of three values: the destination address and two payload
+    /// values, `x` and `y`. Machines are addressed by their index, and get their address as
+    /// their first input. A machine that reads from an empty queue gets `idle_input`.
+    Packets { idle_input: MemCell },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetEvent {
+    /// A value output by the last machine of a chain or loop.
+    Output { from: usize, value: MemCell },
+    /// A packet sent to an address outside the network.
+    Packet {
+        from: usize,
+        to: MemCell,
+        x: MemCell,
+        y: MemCell,
+    },
+    /// All running machines are waiting for input and nothing is in flight. Values sent
+    /// with `Network::send()` get the machines going again.
+    Idle,
+    /// All machines have halted.
+    Halted,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    vm: Intcode,
+    halted: bool,
+    /// Set when the machine reads from an empty queue, reset when it is sent something.
+    blocked: bool,
+    /// Unfinished packet.
+    packet: Vec<MemCell>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Network {
+    nodes: Vec<Node>,
+    wiring: Wiring,
+    next: usize,
+    events: VecDeque<NetEvent>,
+}
+
+impl Network {
+    pub fn new(machines: Vec<Intcode>, wiring: Wiring) -> Self {
+        let mut nodes: Vec<_> = machines
+            .into_iter()
+            .map(|vm| Node {
+                vm,
+                halted: false,
+                blocked: false,
+                packet: vec![],
+            })
+            .collect();
+        if let Wiring::Packets { .. } = wiring {
+            for (addr, node) in nodes.iter_mut().enumerate() {
+                node.vm.push_input(addr as MemCell);
+            }
+        }
+        Self {
+            nodes,
+            wiring,
+            next: 0,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn machine(&self, n: usize) -> &Intcode {
+        &self.nodes[n].vm
+    }
+
+    /// Queues input for machine `n`.
+    pub fn send(&mut self, n: usize, values: &[MemCell]) {
+        let node = &mut self.nodes[n];
+        for &val in values {
+            node.vm.push_input(val);
+        }
+        node.blocked = false;
+    }
+
+    fn is_idle(&self) -> bool {
+        self.nodes.iter().all(|node| {
+            node.halted || (node.blocked && node.vm.queued_input() == 0 && node.packet.is_empty())
+        })
+    }
+
+    /// Runs the network until the next event.
+    pub fn run(&mut self) -> Result<NetEvent, IntcodeError> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            if self.nodes.iter().all(|node| node.halted) {
+                return Ok(NetEvent::Halted);
+            }
+            if self.is_idle() {
+                return Ok(NetEvent::Idle);
+            }
+            let n = self.next;
+            self.next = (n + 1) % self.nodes.len();
+            self.run_machine(n)?;
+        }
+    }
+
+    /// Runs the network until `stop` returns true for an event, or the network halts.
+    /// Returns the last event. Note that `Idle` repeats until something is sent, so `stop`
+    /// should accept it unless the network is known to never go idle.
+    pub fn run_until<F>(&mut self, mut stop: F) -> Result<NetEvent, IntcodeError>
+    where
+        F: FnMut(&NetEvent) -> bool,
+    {
+        loop {
+            let event = self.run()?;
+            if event == NetEvent::Halted || stop(&event) {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Runs machine `n` until it blocks on input or halts.
+    fn run_machine(&mut self, n: usize) -> Result<(), IntcodeError> {
+        let node = &mut self.nodes[n];
+        if node.halted {
+            return Ok(());
+        }
+        if node.blocked && node.vm.queued_input() == 0 {
+            match self.wiring {
+                Wiring::Packets { idle_input } => node.vm.push_input(idle_input),
+                _ => return Ok(()),
+            }
+        }
+        node.blocked = false;
+        loop {
+            let node = &mut self.nodes[n];
+            if node.vm.wants_input() {
+                node.blocked = true;
+                return Ok(());
+            }
+            match node.vm.step()? {
+                None => {}
+                Some(PauseCause::Halt) => {
+                    node.halted = true;
+                    return Ok(());
+                }
+                Some(PauseCause::Output(val)) => self.route(n, val),
+                Some(PauseCause::Input(_)) => unreachable!("Checked by wants_input()"),
+            }
+        }
+    }
+
+    fn route(&mut self, from: usize, value: MemCell) {
+        let last = from + 1 == self.nodes.len();
+        match self.wiring {
+            Wiring::Chain | Wiring::Loop if last => {
+                self.events.push_back(NetEvent::Output { from, value });
+                if self.wiring == Wiring::Loop {
+                    self.send(0, &[value]);
+                }
+            }
+            Wiring::Chain | Wiring::Loop => self.send(from + 1, &[value]),
+            Wiring::Packets { .. } => {
+                let packet = &mut self.nodes[from].packet;
+                packet.push(value);
+                if let [to, x, y] = packet[..] {
+                    packet.clear();
+                    match usize::try_from(to) {
+                        Ok(addr) if addr < self.nodes.len() => self.send(addr, &[x, y]),
+                        _ => self.events.push_back(NetEvent::Packet { from, to, x, y }),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn wiring() {
+    let double = Intcode::from_asm(
+        "
+        in [x]
+        mul [x], #2, [x]
+        out [x]
+        hlt
+x:      .data 0",
+    )
+    .unwrap();
+    let mut net = Network::new(vec![double; 3], Wiring::Chain);
+    net.send(0, &[5]);
+    assert_eq!(net.run().unwrap(), NetEvent::Output { from: 2, value: 40 });
+    assert_eq!(net.run().unwrap(), NetEvent::Halted);
+
+    // Each machine adds its address to x and passes the packet on, starting at machine 0.
+    let relay = Intcode::from_asm(
+        "
+        in [addr]
+        add [addr], #1, [dest]
+        jz [addr], #send
+wait:   in [x]
+        eq [x], #-1, [t]
+        jnz [t], #wait
+        in [y]
+        add [x], [addr], [x]
+send:   out [dest]
+        out [x]
+        out [y]
+idle:   in [t]
+        jz #0, #idle
+addr:   .data 0
+dest:   .data 0
+x:      .data 0
+y:      .data 7
+t:      .data 0",
+    )
+    .unwrap();
+    let mut net = Network::new(vec![relay.clone(); 4], Wiring::Packets { idle_input: -1 });
+    let packet = NetEvent::Packet {
+        from: 3,
+        to: 4,
+        x: 6,
+        y: 7,
+    };
+    assert_eq!(net.run().unwrap(), packet);
+    assert_eq!(net.run().unwrap(), NetEvent::Idle);
+
+    let mut net = Network::new(vec![relay; 4], Wiring::Packets { idle_input: -1 });
+    let event = net.run_until(|e| *e == NetEvent::Idle).unwrap();
+    assert_eq!(event, NetEvent::Idle);
+    assert_eq!(net.machine(3).peek(37), 6);
+}
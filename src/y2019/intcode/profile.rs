@@ -0,0 +1,234 @@
+//! Instruction level profiling and coverage of Intcode programs.
+//!
+//! `Intcode::run_profiled()` counts how often each instruction is executed, which way the
+//! conditional jumps go and how often each memory cell is read and written. The counts
+//! can be laid over a disassembly to find dead code and hot loops.
+
+use super::disasm::{ItemKind, Listing};
+use super::{Event, Intcode, IntcodeError, MemCell, Mode, Op, PauseCause};
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct BranchCount {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    /// Executions by instruction address.
+    pub executions: BTreeMap<usize, u64>,
+    /// Executions by operation.
+    pub opcodes: BTreeMap<Op, u64>,
+    /// Conditional jumps by address.
+    pub branches: BTreeMap<usize, BranchCount>,
+    /// Parameter reads by cell. Reading the instructions themselves isn't counted.
+    pub reads: BTreeMap<usize, u64>,
+    pub writes: BTreeMap<usize, u64>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.executions.values().sum()
+    }
+
+    /// The `n` most executed addresses, most executed first.
+    pub fn hottest(&self, n: usize) -> Vec<(usize, u64)> {
+        let mut hot: Vec<_> = self.executions.iter().map(|(&a, &c)| (a, c)).collect();
+        hot.sort_by_key(|&(addr, count)| (std::cmp::Reverse(count), addr));
+        hot.truncate(n);
+        hot
+    }
+
+    /// Instructions in `listing` that were never executed.
+    pub fn dead_code(&self, listing: &Listing) -> Vec<usize> {
+        listing
+            .items
+            .iter()
+            .filter(|item| matches!(item.kind, ItemKind::Instr(_)))
+            .map(|item| item.addr)
+            .filter(|addr| !self.executions.contains_key(addr))
+            .collect()
+    }
+
+    /// Prefixes each line of `listing` with its execution count, or `-` for instructions
+    /// that were never executed. Conditional jumps are annotated with how often they were
+    /// taken, and jumps back to an immediate lower address are marked as loops, as the
+    /// target of other jumps isn't known from the listing. Data lines get the
+    /// number of reads and writes of their cells.
+    pub fn annotate(&self, listing: &Listing) -> String {
+        let count = |map: &BTreeMap<usize, u64>, addr| map.get(&addr).copied().unwrap_or(0);
+        let mut out = String::new();
+        for item in &listing.items {
+            let mut notes = vec![];
+            let executed = match &item.kind {
+                ItemKind::Instr(instr) => {
+                    if let Some(b) = self.branches.get(&item.addr) {
+                        notes.push(format!("taken {}/{}", b.taken, b.taken + b.not_taken));
+                        let target = &instr.params[1];
+                        let backwards = target.value >= 0 && target.value as usize <= item.addr;
+                        if b.taken > 0 && target.mode == Mode::Imm && backwards {
+                            notes.push("loop".to_string());
+                        }
+                    }
+                    match self.executions.get(&item.addr) {
+                        Some(n) => n.to_string(),
+                        None => "-".to_string(),
+                    }
+                }
+                ItemKind::Data(_) => {
+                    let cells = item.addr..item.addr + item.len();
+                    let reads: u64 = cells.clone().map(|a| count(&self.reads, a)).sum();
+                    let writes: u64 = cells.map(|a| count(&self.writes, a)).sum();
+                    if reads + writes > 0 {
+                        notes.push(format!("r {} w {}", reads, writes));
+                    }
+                    String::new()
+                }
+            };
+            let line = item.to_string();
+            match notes.is_empty() {
+                true => writeln!(out, "{:>8} {}", executed, line),
+                false => writeln!(out, "{:>8} {:<40} ; {}", executed, line, notes.join(", ")),
+            }
+            .unwrap();
+        }
+        out
+    }
+}
+
+impl Intcode {
+    fn exec_profiled(&mut self, profile: &mut Profile) -> Result<Event, IntcodeError> {
+        let ip = self.ip;
+        let instr = self.decode_next();
+        let mut reads = vec![];
+        let mut write = None;
+        let mut branch = None;
+        if let Some(instr) = &instr {
+            let write_param = instr.write_param();
+            for (n, p) in instr.params.iter().enumerate() {
+                match self.param_addr(p) {
+                    Some(addr) if Some(n) == write_param => write = Some(addr),
+                    Some(addr) => reads.push(addr),
+                    None => {}
+                }
+            }
+            let cond = |p| match self.param_addr(p) {
+                Some(addr) => self.mem.get(addr),
+                None => p.value,
+            };
+            branch = match instr.op {
+                Op::Jnz => Some(cond(&instr.params[0]) != 0),
+                Op::Jz => Some(cond(&instr.params[0]) == 0),
+                _ => None,
+            };
+        }
+
+        let event = self.exec()?;
+
+        *profile.executions.entry(ip).or_default() += 1;
+        if let Some(instr) = &instr {
+            *profile.opcodes.entry(instr.op).or_default() += 1;
+        }
+        for addr in reads {
+            *profile.reads.entry(addr).or_default() += 1;
+        }
+        if let Some(addr) = write {
+            *profile.writes.entry(addr).or_default() += 1;
+        }
+        if let Some(taken) = branch {
+            let count = profile.branches.entry(ip).or_default();
+            match taken {
+                true => count.taken += 1,
+                false => count.not_taken += 1,
+            }
+        }
+        Ok(event)
+    }
+
+    /// Same as `run()`, but adds the executed instructions to `profile`.
+    pub fn run_profiled(&mut self, profile: &mut Profile) -> Result<PauseCause<'_>, IntcodeError> {
+        let event = loop {
+            match self.exec_profiled(profile)? {
+                Event::Continue => {}
+                event => break event,
+            }
+        };
+        Ok(self.pause(event).unwrap())
+    }
+
+    /// Same as `run_until_end()`, but adds the executed instructions to `profile`.
+    pub fn run_until_end_profiled(
+        &mut self,
+        input: &[MemCell],
+        profile: &mut Profile,
+    ) -> Result<Vec<MemCell>, IntcodeError> {
+        self.run_to_halt(input, |vm| vm.exec_profiled(profile))
+    }
+}
+
+#[test]
+fn coverage() {
+    let prog = Intcode::from_asm(
+        "
+        in [count]
+loop:   add [total], [count], [total]
+        add [count], #-1, [count]
+        jnz [count], #loop
+        lt [total], #0, [neg]
+        jnz [neg], #dead
+        out [total]
+        hlt
+dead:   out #-1
+        hlt
+count:  .data 0
+total:  .data 0
+neg:    .data 0",
+    )
+    .unwrap();
+    let mut profile = Profile::new();
+    let mut vm = prog.clone();
+    assert_eq!(vm.run_until_end_profiled(&[3], &mut profile).unwrap(), [6]);
+    assert_eq!(profile.total(), 1 + 3 * 3 + 4);
+    assert_eq!(profile.opcodes[&Op::Add], 6);
+    assert_eq!(profile.hottest(2), [(2, 3), (6, 3)]);
+    let taken = |taken, not_taken| BranchCount { taken, not_taken };
+    assert_eq!(profile.branches[&10], taken(2, 1));
+    assert_eq!(profile.branches[&17], taken(0, 1));
+    assert_eq!((profile.reads[&26], profile.writes[&26]), (9, 4));
+
+    let listing = prog.disassemble();
+    assert_eq!(profile.dead_code(&listing), [23, 25]);
+    assert_eq!(
+        profile.annotate(&listing),
+        "       1     0:         in [26]
+       3     2: L2:     add [27], [26], [27]
+       3     6:         add [26], #-1, [26]
+       3    10:         jnz [26], #L2             ; taken 2/3, loop
+       1    13:         lt [27], #0, [28]
+       1    17:         jnz [28], #L23            ; taken 0/1
+       1    20:         out [27]
+       1    22:         hlt
+       -    23: L23:    out #-1
+       -    25:         hlt
+            26:         .data 0, 0, 0             ; r 15 w 8
+"
+    );
+
+    // The jump target is read from address 2, which holds 7.
+    let prog = Intcode::load_program("1101,0,7,8,105,1,2,99,0");
+    let mut profile = Profile::new();
+    prog.clone()
+        .run_until_end_profiled(&[], &mut profile)
+        .unwrap();
+    let annotated = profile.annotate(&prog.disassemble());
+    assert!(annotated.contains("jnz #1, [2]"), "{}", annotated);
+    assert!(annotated.contains("taken 1/1"), "{}", annotated);
+    assert!(!annotated.contains("loop"), "{}", annotated);
+}
@@ -0,0 +1,319 @@
+//! Harness for Intcode programs that control a robot in a 2D world, like the hull painting
+//! robot, the arcade cabinet and the repair droid of 2019.
+//!
+//! The VM's output is cut into commands of a fixed number of values, which a `Protocol`
+//! applies to the `Robot`. When the VM needs input, the protocol provides it, usually a
+//! sensor reading or the move chosen by a controller. The world can be a `HashMap` for
+//! open-ended exploration, or a `Grid` if the size is known.
+
+use super::{ErrorKind, Intcode, IntcodeError, MemCell, PauseCause};
+use crate::grid::{Coord, Grid};
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// Storage for the tiles of the world.
+pub trait World {
+    fn tile(&self, c: Coord) -> Option<MemCell>;
+    fn set_tile(&mut self, c: Coord, tile: MemCell);
+}
+
+impl World for HashMap<Coord, MemCell> {
+    fn tile(&self, c: Coord) -> Option<MemCell> {
+        self.get(&c).copied()
+    }
+
+    fn set_tile(&mut self, c: Coord, tile: MemCell) {
+        self.insert(c, tile);
+    }
+}
+
+/// Writes outside the grid are ignored.
+impl World for Grid<MemCell> {
+    fn tile(&self, c: Coord) -> Option<MemCell> {
+        self.get(c).copied()
+    }
+
+    fn set_tile(&mut self, c: Coord, tile: MemCell) {
+        if let Some(t) = self.get_mut(c) {
+            *t = tile;
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Robot<W> {
+    pub world: W,
+    pub pos: Coord,
+    /// Facing direction, with y pointing down.
+    pub dir: Coord,
+    /// Visited positions, starting with the start position.
+    pub path: Vec<Coord>,
+}
+
+impl<W: World> Robot<W> {
+    /// Creates a robot at the origin, facing up.
+    pub fn new(world: W) -> Self {
+        Self {
+            world,
+            pos: Coord::new(0, 0),
+            dir: Coord::new(0, -1),
+            path: vec![Coord::new(0, 0)],
+        }
+    }
+
+    pub fn move_to(&mut self, pos: Coord) {
+        self.pos = pos;
+        self.path.push(pos);
+    }
+}
+
+/// Translates between the VM and the robot.
+pub trait Protocol<W> {
+    /// The number of output values per command.
+    fn arity(&self) -> usize;
+    /// The next input value, or `None` to stop the harness.
+    fn input(&mut self, robot: &Robot<W>) -> Option<MemCell>;
+    /// Fails if the command can't be applied, such as a coordinate out of range.
+    fn apply(&mut self, robot: &mut Robot<W>, command: &[MemCell]) -> Result<(), ErrorKind>;
+}
+
+/// Input is the color of the current tile, 0 if unpainted. Commands are a color to paint
+/// and a turn, 0 for left and 1 for right, followed by a step forward.
+pub struct PaintTurn;
+
+impl<W: World> Protocol<W> for PaintTurn {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn input(&mut self, robot: &Robot<W>) -> Option<MemCell> {
+        Some(robot.world.tile(robot.pos).unwrap_or(0))
+    }
+
+    fn apply(&mut self, robot: &mut Robot<W>, command: &[MemCell]) -> Result<(), ErrorKind> {
+        robot.world.set_tile(robot.pos, command[0]);
+        robot.dir = match command[1] {
+            0 => robot.dir.rotate_ccw(),
+            _ => robot.dir.rotate_cw(),
+        };
+        robot.move_to(robot.pos + robot.dir);
+        Ok(())
+    }
+}
+
+/// Movement commands 1 to 4 for north, south, west and east.
+pub fn move_command(dir: Coord) -> MemCell {
+    match (dir.x, dir.y) {
+        (0, -1) => 1,
+        (0, 1) => 2,
+        (-1, 0) => 3,
+        (1, 0) => 4,
+        _ => panic!("Not a single step: {:?}", dir),
+    }
+}
+
+/// The controller picks a direction to move in, which is sent as a `move_command()`. The
+/// VM answers with a status: 0 if the robot hit a wall, 1 if it moved and 2 if it moved and
+/// found the target. The status is stored in the world at the tile the robot tried to
+/// move to.
+pub struct MoveStatus<F> {
+    controller: F,
+    dir: Coord,
+}
+
+impl<F> MoveStatus<F> {
+    pub fn new(controller: F) -> Self {
+        Self {
+            controller,
+            dir: Coord::new(0, 0),
+        }
+    }
+}
+
+impl<W: World, F: FnMut(&Robot<W>) -> Option<Coord>> Protocol<W> for MoveStatus<F> {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn input(&mut self, robot: &Robot<W>) -> Option<MemCell> {
+        self.dir = (self.controller)(robot)?;
+        Some(move_command(self.dir))
+    }
+
+    fn apply(&mut self, robot: &mut Robot<W>, command: &[MemCell]) -> Result<(), ErrorKind> {
+        let target = robot.pos + self.dir;
+        robot.world.set_tile(target, command[0]);
+        if command[0] != 0 {
+            robot.dir = self.dir;
+            robot.move_to(target);
+        }
+        Ok(())
+    }
+}
+
+/// Commands are `x, y, tile` triples that draw on the world. `-1, 0, score` sets the score
+/// instead. Input comes from the controller, such as a joystick position.
+pub struct Triples<F> {
+    controller: F,
+    pub score: Option<MemCell>,
+}
+
+impl<F> Triples<F> {
+    pub fn new(controller: F) -> Self {
+        Self {
+            controller,
+            score: None,
+        }
+    }
+}
+
+impl<W: World, F: FnMut(&Robot<W>) -> Option<MemCell>> Protocol<W> for Triples<F> {
+    fn arity(&self) -> usize {
+        3
+    }
+
+    fn input(&mut self, robot: &Robot<W>) -> Option<MemCell> {
+        (self.controller)(robot)
+    }
+
+    fn apply(&mut self, robot: &mut Robot<W>, command: &[MemCell]) -> Result<(), ErrorKind> {
+        match *command {
+            [-1, 0, score] => self.score = Some(score),
+            [x, y, tile] => {
+                let coord = |v| i32::try_from(v).map_err(|_| ErrorKind::Overflow);
+                robot.world.set_tile(Coord::new(coord(x)?, coord(y)?), tile);
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HarnessEnd {
+    Halted,
+    /// The protocol returned no input.
+    Stopped,
+}
+
+pub struct Harness<W, P> {
+    pub vm: Intcode,
+    pub robot: Robot<W>,
+    pub protocol: P,
+    /// Output of an unfinished command.
+    pending: Vec<MemCell>,
+}
+
+impl<W: World, P: Protocol<W>> Harness<W, P> {
+    pub fn new(vm: Intcode, world: W, protocol: P) -> Self {
+        Self {
+            vm,
+            robot: Robot::new(world),
+            protocol,
+            pending: vec![],
+        }
+    }
+
+    /// Runs until the program halts or the protocol stops it. A stopped harness can be
+    /// run again. A command the protocol can't apply is reported as an error of the
+    /// output instruction that completed it.
+    pub fn run(&mut self) -> Result<HarnessEnd, IntcodeError> {
+        loop {
+            if self.vm.wants_input() {
+                match self.protocol.input(&self.robot) {
+                    Some(val) => self.vm.push_input(val),
+                    None => return Ok(HarnessEnd::Stopped),
+                }
+            }
+            let ip = self.vm.ip();
+            match self.vm.step()? {
+                None => {}
+                Some(PauseCause::Halt) => return Ok(HarnessEnd::Halted),
+                Some(PauseCause::Output(val)) => {
+                    self.pending.push(val);
+                    if self.pending.len() == self.protocol.arity() {
+                        let applied = self.protocol.apply(&mut self.robot, &self.pending);
+                        self.pending.clear();
+                        applied.map_err(|kind| IntcodeError {
+                            ip,
+                            opcode: self.vm.memory().get(ip),
+                            kind,
+                        })?;
+                    }
+                }
+                Some(PauseCause::Input(_)) => unreachable!("Checked by wants_input()"),
+            }
+        }
+    }
+}
+
+#[test]
+fn protocols() {
+    // Paints the current tile if it is unpainted and turns right, four times.
+    let painter = Intcode::from_asm(
+        "
+loop:   in [color]
+        eq [color], #0, [color]
+        out [color]
+        out #1
+        add [n], #-1, [n]
+        jnz [n], #loop
+        hlt
+color:  .data 0
+n:      .data 4",
+    )
+    .unwrap();
+    let mut world = HashMap::new();
+    world.insert(Coord::new(0, -1), 1);
+    let mut h = Harness::new(painter, world, PaintTurn);
+    assert_eq!(h.run().unwrap(), HarnessEnd::Halted);
+    assert_eq!(h.robot.path.len(), 5);
+    assert_eq!(h.robot.pos, Coord::new(0, 0));
+    let (grid, top_left) = Grid::from_sparse(&h.robot.world, 0).unwrap();
+    assert_eq!(
+        (grid.to_string(), top_left),
+        ("10\n11\n11\n".to_string(), Coord::new(0, -1))
+    );
+
+    // A corridor to the east, with walls everywhere else.
+    let corridor = Intcode::from_asm(
+        "
+loop:   in [m]
+        eq [m], #4, [m]
+        out [m]
+        jz #0, #loop
+m:      .data 0",
+    )
+    .unwrap();
+    let mut moves = vec![Coord::new(0, -1), Coord::new(1, 0)]
+        .into_iter()
+        .cycle()
+        .take(6);
+    let controller = |_: &Robot<_>| moves.next();
+    let mut h = Harness::new(
+        corridor,
+        Grid::new(vec![-1; 8], 4, 2),
+        MoveStatus::new(controller),
+    );
+    assert_eq!(h.run().unwrap(), HarnessEnd::Stopped);
+    assert_eq!(h.robot.pos, Coord::new(3, 0));
+    assert_eq!(h.robot.path.len(), 4);
+    assert_eq!(h.robot.world.to_string(), "-1111\n-1-1-1-1\n");
+
+    let screen = Intcode::load_program("104,1,104,1,104,2,104,-1,104,0,104,42,99");
+    let mut h = Harness::new(screen, HashMap::new(), Triples::new(|_: &Robot<_>| None));
+    assert_eq!(h.run().unwrap(), HarnessEnd::Halted);
+    assert_eq!(h.robot.world[&Coord::new(1, 1)], 2);
+    assert_eq!(h.protocol.score, Some(42));
+
+    let off_screen = Intcode::load_program("104,1,104,4294967296,104,2,99");
+    let mut h = Harness::new(
+        off_screen,
+        HashMap::new(),
+        Triples::new(|_: &Robot<_>| None),
+    );
+    let e = h.run().unwrap_err();
+    assert_eq!((e.ip, e.opcode, e.kind), (4, 104, ErrorKind::Overflow));
+    assert!(h.robot.world.is_empty());
+}
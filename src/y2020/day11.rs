@@ -2,6 +2,7 @@ use std::convert::{TryFrom, TryInto};
 use std::fmt::{Display, Formatter};
 
 use crate::grid::*;
+use itertools::Itertools;
 
 const INPUT_FILE: &str = "data/2020/day11.txt";
 
@@ -47,40 +48,29 @@ impl Display for Tile {
 }
 
 pub fn part1(grid: &TileGrid) -> usize {
-    let mut next = (*grid).clone();
-    for _ in 0..100 {
-        let grid = next;
-        next = grid.clone();
-        'coords: for coord in grid.coords() {
+    let mut next = TrackedGrid::new((*grid).clone());
+    let mut frontier: Vec<Coord> = grid.coords().collect();
+    while !frontier.is_empty() {
+        let grid = (*next).clone();
+        for coord in frontier {
+            let occupied = grid
+                .neighbours(coord)
+                .filter(|&&t| t == Tile::Occupied)
+                .count();
             match grid[coord] {
-                Tile::Floor => {}
-                Tile::Occupied => {
-                    let mut cnt = 0;
-                    for t in grid.neighbours(coord) {
-                        match t {
-                            Tile::Occupied => cnt += 1,
-                            Tile::Floor => continue,
-                            Tile::Chair => {}
-                        }
-                        if cnt >= 4 {
-                            next[coord] = Tile::Chair;
-                            break;
-                        }
-                    }
-                }
-                Tile::Chair => {
-                    for t in grid.neighbours(coord) {
-                        if matches!(t, &Tile::Occupied) {
-                            continue 'coords;
-                        }
-                    }
-                    next[coord] = Tile::Occupied
-                }
+                Tile::Chair if occupied == 0 => next[coord] = Tile::Occupied,
+                Tile::Occupied if occupied >= 4 => next[coord] = Tile::Chair,
+                _ => {}
             }
         }
-        if next == grid {
-            break;
-        }
+        // Only the seats next to a changed seat can change in the next round.
+        let changed = next.take_dirty();
+        frontier = changed
+            .iter()
+            .flat_map(|&c| grid.neighbour_coords(c))
+            .chain(changed.iter().copied())
+            .unique()
+            .collect();
     }
     next.iter_tiles()
         .filter(|(_c, &t)| t == Tile::Occupied)
@@ -88,52 +78,22 @@ pub fn part1(grid: &TileGrid) -> usize {
 }
 
 pub fn part2(input: &TileGrid) -> usize {
-    let directions = [
-        (1, 0),
-        (-1, 0),
-        (0, 1),
-        (0, -1),
-        (1, 1),
-        (1, -1),
-        (-1, 1),
-        (-1, -1),
-    ];
     let mut next = (*input).clone();
     for _ in 0..100 {
         let grid = next;
         next = grid.clone();
-        'next_tile: for c in grid.coords() {
+        for c in grid.coords() {
+            if grid[c] == Tile::Floor {
+                continue;
+            }
+            let visible = grid
+                .rays(c, |&t| t != Tile::Floor)
+                .filter(|(_, &t)| t == Tile::Occupied)
+                .count();
             match grid[c] {
-                Tile::Floor => {}
-                Tile::Chair => {
-                    for d in &directions {
-                        for t in grid.line(c, *d) {
-                            match t {
-                                Tile::Occupied => continue 'next_tile,
-                                Tile::Floor => continue,
-                                Tile::Chair => break,
-                            }
-                        }
-                    }
-                    next[c] = Tile::Occupied;
-                }
-                Tile::Occupied => {
-                    let mut cnt = 0;
-                    for d in &directions {
-                        for t in grid.line(c, *d) {
-                            match t {
-                                Tile::Occupied => cnt += 1,
-                                Tile::Floor => continue,
-                                Tile::Chair => {}
-                            }
-                            break;
-                        }
-                        if cnt >= 5 {
-                            next[c] = Tile::Chair;
-                            break;
-                        }
-                    }
-                }
+                Tile::Chair if visible == 0 => next[c] = Tile::Occupied,
+                Tile::Occupied if visible >= 5 => next[c] = Tile::Chair,
+                _ => {}
             }
         }
 
@@ -1,8 +1,24 @@
-use std::slice::Iter;
+//! Arithmetic with unusual operator precedence.
+//!
+//! Expressions are parsed with a Pratt parser, which takes the precedence and
+//! associativity of each binary operator from an `OpTable`. Unary minus binds tighter than
+//! any binary operator.
+//!
+//! The trees can be printed fully parenthesized, with `Display`, or as an indented tree,
+//! which shows how the same line is grouped under different tables. Expressions may also
+//! contain single letter variables, which are kept as they are when constants are folded.
+
+use std::fmt::{Display, Formatter};
 
 const INPUT_FILE: &str = "data/2020/day18.txt";
 
-pub type Expression = Vec<Token>;
+/// Limit on the depth of expression trees, and on the nesting of parentheses, unary minus
+/// and right associative operators while parsing. Deeper expressions would overflow the
+/// stack, when parsed or when the tree is evaluated, printed or dropped.
+const MAX_DEPTH: usize = 1000;
+
+/// Tokens of an expression, with their position in the line.
+pub type Expression = Vec<(usize, Token)>;
 pub type MathProblems = Vec<Expression>;
 
 pub fn load_input<L: IntoIterator<Item = S>, S: AsRef<str>>(line_source: L) -> MathProblems {
@@ -10,109 +26,340 @@ pub fn load_input<L: IntoIterator<Item = S>, S: AsRef<str>>(line_source: L) -> M
 }
 
 fn parse<S: AsRef<str>>(s: S) -> Expression {
-    s.as_ref()
-        .chars()
-        .filter(|c| !c.is_ascii_whitespace())
-        .map(|c| {
-            use Token::*;
-            match c {
-                '(' => LPar,
-                ')' => RPar,
-                '+' => Add,
-                '*' => Mul,
-                d if d.is_ascii_digit() => Digit(d.to_digit(10).unwrap() as u64),
-                _ => WS,
-            }
-        })
-        .collect()
+    tokenize(s.as_ref()).unwrap()
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Token {
-    Digit(u64),
-    Add,
-    Mul,
+    Num(i64),
+    Var(char),
+    Op(BinOp),
     LPar,
     RPar,
-    WS,
 }
 
-fn eval_rhs1(iter: &mut Iter<Token>) -> u64 {
-    while let Some(t) = iter.next() {
-        match t {
-            Token::Digit(d) => return *d,
-            Token::LPar => return eval1(iter),
-            Token::WS => continue,
-            _ => panic!("Unexpected token"),
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinOp {
+    pub fn symbol(self) -> char {
+        match self {
+            BinOp::Add => '+',
+            BinOp::Sub => '-',
+            BinOp::Mul => '*',
+            BinOp::Div => '/',
         }
     }
-    0
+
+    fn apply(self, a: i64, b: i64) -> Result<i64, ExprError> {
+        let val = match self {
+            BinOp::Add => a.checked_add(b),
+            BinOp::Sub => a.checked_sub(b),
+            BinOp::Mul => a.checked_mul(b),
+            BinOp::Div if b == 0 => return Err(ExprError::DivisionByZero),
+            BinOp::Div => a.checked_div(b),
+        };
+        val.ok_or(ExprError::Overflow)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExprError {
+    /// Character that isn't part of any token, and its position.
+    BadChar(usize, char),
+    /// Position of a parenthesis without a match.
+    UnbalancedParen(usize),
+    UnexpectedToken(usize),
+    UnexpectedEnd,
+    TooDeep,
+    Unbound(char),
+    DivisionByZero,
+    Overflow,
 }
 
-fn eval1(expr: &mut Iter<Token>) -> u64 {
-    let mut lhs = eval_rhs1(expr);
-    while let Some(t) = expr.next() {
-        match t {
-            Token::Digit(d) => lhs = *d,
-            Token::Add => lhs += eval_rhs1(expr),
-            Token::Mul => lhs *= eval_rhs1(expr),
-            Token::RPar => return lhs,
-            Token::WS => continue,
-            _ => panic!("Unexpected token"),
+impl Display for ExprError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExprError::BadChar(pos, c) => write!(f, "bad character '{}' at {}", c, pos),
+            ExprError::UnbalancedParen(pos) => write!(f, "unbalanced parenthesis at {}", pos),
+            ExprError::UnexpectedToken(pos) => write!(f, "unexpected token at {}", pos),
+            ExprError::UnexpectedEnd => write!(f, "unexpected end of expression"),
+            ExprError::TooDeep => write!(f, "expression nested too deeply"),
+            ExprError::Unbound(v) => write!(f, "variable {} has no value", v),
+            ExprError::DivisionByZero => write!(f, "division by zero"),
+            ExprError::Overflow => write!(f, "overflow"),
         }
     }
-    lhs
 }
 
-pub fn part1(expressions: &MathProblems) -> u64 {
-    let mut sum = 0;
-    for expr in expressions {
-        sum += eval1(&mut expr.iter());
+impl std::error::Error for ExprError {}
+
+pub fn tokenize(s: &str) -> Result<Expression, ExprError> {
+    let mut tokens = vec![];
+    let mut chars = s.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        let token = match c {
+            '(' => Token::LPar,
+            ')' => Token::RPar,
+            '+' => Token::Op(BinOp::Add),
+            '-' => Token::Op(BinOp::Sub),
+            '*' => Token::Op(BinOp::Mul),
+            '/' => Token::Op(BinOp::Div),
+            c if c.is_ascii_whitespace() => continue,
+            c if c.is_ascii_alphabetic() => Token::Var(c),
+            c if c.is_ascii_digit() => {
+                let mut n = c.to_digit(10).unwrap() as i64;
+                while let Some(d) = chars.peek().and_then(|(_, c)| c.to_digit(10)) {
+                    chars.next();
+                    n = n
+                        .checked_mul(10)
+                        .and_then(|n| n.checked_add(d as i64))
+                        .ok_or(ExprError::Overflow)?;
+                }
+                Token::Num(n)
+            }
+            c => return Err(ExprError::BadChar(pos, c)),
+        };
+        tokens.push((pos, token));
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+/// Precedence and associativity of the binary operators. Higher levels bind tighter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OpTable([(u8, Assoc); 4]);
+
+impl OpTable {
+    /// All operators at the same level, evaluated left to right.
+    pub fn uniform() -> Self {
+        Self([(1, Assoc::Left); 4])
+    }
+
+    /// The usual rules, with multiplication and division before addition and subtraction.
+    pub fn standard() -> Self {
+        Self::uniform()
+            .with(BinOp::Mul, 2, Assoc::Left)
+            .with(BinOp::Div, 2, Assoc::Left)
+    }
+
+    /// The rules of part 2, with addition and subtraction first.
+    pub fn addition_first() -> Self {
+        Self::uniform()
+            .with(BinOp::Add, 2, Assoc::Left)
+            .with(BinOp::Sub, 2, Assoc::Left)
+    }
+
+    pub fn with(mut self, op: BinOp, level: u8, assoc: Assoc) -> Self {
+        self.0[op as usize] = (level, assoc);
+        self
+    }
+
+    pub fn get(&self, op: BinOp) -> (u8, Assoc) {
+        self.0[op as usize]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Num(i64),
+    Var(char),
+    Neg(Box<Expr>),
+    Bin(BinOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn eval(&self) -> Result<i64, ExprError> {
+        match self {
+            Expr::Num(n) => Ok(*n),
+            Expr::Var(v) => Err(ExprError::Unbound(*v)),
+            Expr::Neg(e) => e.eval()?.checked_neg().ok_or(ExprError::Overflow),
+            Expr::Bin(op, a, b) => op.apply(a.eval()?, b.eval()?),
+        }
+    }
+
+    /// Replaces the subtrees without variables by their value. Subtrees that fail to
+    /// evaluate, such as a division by zero, are left as they are.
+    pub fn fold(&self) -> Expr {
+        let folded = match self {
+            Expr::Num(_) | Expr::Var(_) => return self.clone(),
+            Expr::Neg(e) => Expr::Neg(Box::new(e.fold())),
+            Expr::Bin(op, a, b) => Expr::Bin(*op, Box::new(a.fold()), Box::new(b.fold())),
+        };
+        match folded.eval() {
+            Ok(n) => Expr::Num(n),
+            Err(_) => folded,
+        }
+    }
+
+    /// The tree with one node per line, and the operands of each operator indented below
+    /// it.
+    pub fn tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(&mut out, 0);
+        out
+    }
+
+    fn write_tree(&self, out: &mut String, depth: usize) {
+        out.push_str(&"  ".repeat(depth));
+        match self {
+            Expr::Num(n) => out.push_str(&format!("{}\n", n)),
+            Expr::Var(v) => out.push_str(&format!("{}\n", v)),
+            Expr::Neg(e) => {
+                out.push_str("neg\n");
+                e.write_tree(out, depth + 1);
+            }
+            Expr::Bin(op, a, b) => {
+                out.push_str(&format!("{}\n", op.symbol()));
+                a.write_tree(out, depth + 1);
+                b.write_tree(out, depth + 1);
+            }
+        }
     }
-    sum
 }
 
-fn eval_rhs2(iter: &mut Iter<Token>) -> u64 {
-    while let Some(t) = iter.next() {
-        match t {
-            Token::Digit(d) => return *d,
-            Token::LPar => return eval2(iter, true),
-            Token::WS => continue,
-            _ => panic!("Unexpected token"),
+/// Fully parenthesized, with every binary operation in its own parentheses.
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Num(n) => write!(f, "{}", n),
+            Expr::Var(v) => write!(f, "{}", v),
+            Expr::Neg(e) => write!(f, "-{}", e),
+            Expr::Bin(op, a, b) => write!(f, "({} {} {})", a, op.symbol(), b),
         }
     }
-    0
 }
 
-fn next_rpar<'a>(expr: &'a mut Iter<Token>, consume_rpar: bool) -> Option<&'a Token> {
-    let s = expr.as_slice();
-    match !s.is_empty() && s[0] == Token::RPar && !consume_rpar {
-        true => None,
-        false => expr.next(),
+/// A parsed subtree and its depth.
+type Parsed = (Expr, usize);
+
+/// Depth of a new node above subtrees of `depth`, if it isn't too deep.
+fn deeper(depth: usize) -> Result<usize, ExprError> {
+    match depth < MAX_DEPTH {
+        true => Ok(depth + 1),
+        false => Err(ExprError::TooDeep),
     }
 }
 
-fn eval2(expr: &mut Iter<Token>, consume_rpar: bool) -> u64 {
-    let mut lhs = eval_rhs2(expr);
-    while let Some(t) = next_rpar(expr, consume_rpar) {
-        match t {
-            Token::Digit(d) => lhs = *d,
-            Token::Add => lhs += eval_rhs2(expr),
-            Token::Mul => lhs *= eval2(expr, false),
-            Token::RPar => return lhs,
-            Token::WS => continue,
-            _ => panic!("Unexpected token"),
+struct Parser<'a> {
+    tokens: &'a [(usize, Token)],
+    next: usize,
+    table: &'a OpTable,
+    /// Current depth of the recursion.
+    nesting: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<(usize, Token)> {
+        self.tokens.get(self.next).copied()
+    }
+
+    /// Calls `parse` one level deeper in the recursion, or fails if that is too deep.
+    fn nested<F>(&mut self, parse: F) -> Result<Parsed, ExprError>
+    where
+        F: FnOnce(&mut Self) -> Result<Parsed, ExprError>,
+    {
+        self.nesting = deeper(self.nesting)?;
+        let parsed = parse(self);
+        self.nesting -= 1;
+        parsed
+    }
+
+    fn primary(&mut self) -> Result<Parsed, ExprError> {
+        let (pos, token) = self.peek().ok_or(ExprError::UnexpectedEnd)?;
+        self.next += 1;
+        match token {
+            Token::Num(n) => Ok((Expr::Num(n), 1)),
+            Token::Var(v) => Ok((Expr::Var(v), 1)),
+            Token::Op(BinOp::Sub) => {
+                let (e, depth) = self.nested(Self::primary)?;
+                Ok((Expr::Neg(Box::new(e)), deeper(depth)?))
+            }
+            Token::LPar => {
+                let inner = self.nested(|p| p.expr(0))?;
+                match self.peek() {
+                    Some((_, Token::RPar)) => {
+                        self.next += 1;
+                        Ok(inner)
+                    }
+                    Some((next, _)) => Err(ExprError::UnexpectedToken(next)),
+                    None => Err(ExprError::UnbalancedParen(pos)),
+                }
+            }
+            Token::RPar => Err(ExprError::UnbalancedParen(pos)),
+            Token::Op(_) => Err(ExprError::UnexpectedToken(pos)),
+        }
+    }
+
+    /// Parses operators with at least `min_level` precedence. The level is wider than in
+    /// the table, so that there is a level above the highest one.
+    fn expr(&mut self, min_level: u16) -> Result<Parsed, ExprError> {
+        let (mut lhs, mut depth) = self.primary()?;
+        while let Some((_, Token::Op(op))) = self.peek() {
+            let (level, assoc) = self.table.get(op);
+            let level = level as u16;
+            if level < min_level {
+                break;
+            }
+            self.next += 1;
+            let (rhs, rhs_depth) = match assoc {
+                Assoc::Left => self.expr(level + 1)?,
+                Assoc::Right => self.nested(|p| p.expr(level))?,
+            };
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(rhs));
+            depth = deeper(depth.max(rhs_depth))?;
         }
+        Ok((lhs, depth))
     }
-    lhs
 }
 
-pub fn part2(expressions: &MathProblems) -> u64 {
-    let mut sum = 0;
-    for expr in expressions {
-        sum += eval2(&mut expr.iter(), false);
+/// Parses the tokens of a whole expression into a tree, with operators grouped by `table`.
+pub fn parse_expr(tokens: &[(usize, Token)], table: &OpTable) -> Result<Expr, ExprError> {
+    let mut parser = Parser {
+        tokens,
+        next: 0,
+        table,
+        nesting: 0,
+    };
+    let (expr, _) = parser.expr(0)?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some((pos, Token::RPar)) => Err(ExprError::UnbalancedParen(pos)),
+        Some((pos, _)) => Err(ExprError::UnexpectedToken(pos)),
     }
-    sum
+}
+
+/// The trees of an expression under the rules of part 1 and part 2.
+pub fn groupings(tokens: &[(usize, Token)]) -> Result<[Expr; 2], ExprError> {
+    Ok([
+        parse_expr(tokens, &OpTable::uniform())?,
+        parse_expr(tokens, &OpTable::addition_first())?,
+    ])
+}
+
+fn sum(expressions: &MathProblems, table: &OpTable) -> i64 {
+    expressions
+        .iter()
+        .map(|e| parse_expr(e, table).unwrap().eval().unwrap())
+        .sum()
+}
+
+pub fn part1(expressions: &MathProblems) -> i64 {
+    sum(expressions, &OpTable::uniform())
+}
+
+pub fn part2(expressions: &MathProblems) -> i64 {
+    sum(expressions, &OpTable::addition_first())
 }
 
 #[test]
@@ -137,3 +384,125 @@ fn test_data() {
     assert_eq!(part1(&d), 71 + 51 + 26 + 437 + 12240 + 13632);
     assert_eq!(part2(&d), 231 + 51 + 46 + 1445 + 669060 + 23340);
 }
+
+#[test]
+fn precedence() {
+    let eval = |s: &str, table: &OpTable| parse_expr(&tokenize(s).unwrap(), table)?.eval();
+    let standard = OpTable::standard();
+    assert_eq!(eval("12 - 20 / 4 * 3", &standard), Ok(-3));
+    assert_eq!(eval("12 - 20 / 4 * 3", &OpTable::uniform()), Ok(-6));
+    assert_eq!(eval("-(2 + 3) * --4", &standard), Ok(-20));
+    assert_eq!(eval("2 - -3 - 4", &standard), Ok(1));
+    let right = standard.with(BinOp::Sub, 1, Assoc::Right);
+    assert_eq!(eval("2 - -3 - 4", &right), Ok(9));
+    assert_eq!(
+        parse_expr(&tokenize("1 + 2 * 3").unwrap(), &OpTable::addition_first()),
+        Ok(Expr::Bin(
+            BinOp::Mul,
+            Box::new(Expr::Bin(
+                BinOp::Add,
+                Box::new(Expr::Num(1)),
+                Box::new(Expr::Num(2))
+            )),
+            Box::new(Expr::Num(3))
+        ))
+    );
+
+    assert_eq!(
+        eval("(1 + 2", &standard),
+        Err(ExprError::UnbalancedParen(0))
+    );
+    assert_eq!(
+        eval("1 + 2) * 3", &standard),
+        Err(ExprError::UnbalancedParen(5))
+    );
+    assert_eq!(eval(")", &standard), Err(ExprError::UnbalancedParen(0)));
+    assert_eq!(eval("(1 2)", &standard), Err(ExprError::UnexpectedToken(3)));
+    assert_eq!(
+        eval("((1) 2", &standard),
+        Err(ExprError::UnexpectedToken(5))
+    );
+    assert_eq!(
+        eval("1 + * 2", &standard),
+        Err(ExprError::UnexpectedToken(4))
+    );
+    assert_eq!(eval("1 2", &standard), Err(ExprError::UnexpectedToken(2)));
+    assert_eq!(eval("1 +", &standard), Err(ExprError::UnexpectedEnd));
+    assert_eq!(
+        eval("7 / (3 - 3)", &standard),
+        Err(ExprError::DivisionByZero)
+    );
+    assert_eq!(
+        eval("9223372036854775807 + 1", &standard),
+        Err(ExprError::Overflow)
+    );
+    assert_eq!(tokenize("2 ^ 3"), Err(ExprError::BadChar(2, '^')));
+
+    let top = standard
+        .with(BinOp::Mul, 255, Assoc::Left)
+        .with(BinOp::Div, 255, Assoc::Right);
+    assert_eq!(eval("2 * 3 * 4 + 1", &top), Ok(25));
+    assert_eq!(eval("64 / 8 / 2", &top), Ok(16));
+    let deep = |open: &str, close: &str, n| format!("{}1{}", open.repeat(n), close.repeat(n));
+    assert_eq!(eval(&deep("(", ")", 500), &standard), Ok(1));
+    assert_eq!(eval(&deep("-", "", 500), &standard), Ok(1));
+    assert_eq!(
+        eval(&deep("(", ")", 100_000), &standard),
+        Err(ExprError::TooDeep)
+    );
+    assert_eq!(
+        eval(&deep("-", "", 100_000), &standard),
+        Err(ExprError::TooDeep)
+    );
+    assert_eq!(
+        eval(&deep("1 - ", "", 100_000), &right),
+        Err(ExprError::TooDeep)
+    );
+    assert_eq!(eval(&deep("1 - ", "", 900), &standard), Ok(-899));
+    assert_eq!(
+        eval(&deep("1 - ", "", 100_000), &standard),
+        Err(ExprError::TooDeep)
+    );
+    assert_eq!(
+        ExprError::UnbalancedParen(5).to_string(),
+        "unbalanced parenthesis at 5"
+    );
+}
+
+#[test]
+fn printing() {
+    let tokens = tokenize("1 + 2 * 3 + 4 * 5 + 6").unwrap();
+    let [p1, p2] = groupings(&tokens).unwrap();
+    assert_eq!(p1.to_string(), "(((((1 + 2) * 3) + 4) * 5) + 6)");
+    assert_eq!(p2.to_string(), "(((1 + 2) * (3 + 4)) * (5 + 6))");
+    assert_eq!(p1.fold(), Expr::Num(71));
+    assert_eq!(p2.fold(), Expr::Num(231));
+    assert_eq!(
+        p2.tree(),
+        "*\n  *\n    +\n      1\n      2\n    +\n      3\n      4\n  +\n    5\n    6\n"
+    );
+
+    let tokens = tokenize("x * (2 + 3) + -(4 * 5) / (y - 1)").unwrap();
+    let [p1, p2] = groupings(&tokens).unwrap();
+    assert_eq!(p1.to_string(), "(((x * (2 + 3)) + -(4 * 5)) / (y - 1))");
+    assert_eq!(p1.fold().to_string(), "(((x * 5) + -20) / (y - 1))");
+    assert_eq!(p2.fold().to_string(), "((x * -15) / (y - 1))");
+    assert_eq!(p2.eval(), Err(ExprError::Unbound('x')));
+    assert_eq!(
+        p2.fold().tree(),
+        "/\n  *\n    x\n    -15\n  -\n    y\n    1\n"
+    );
+
+    let tokens = tokenize("a + 1 / (2 - 2) + 3 * 4").unwrap();
+    let [p1, p2] = groupings(&tokens).unwrap();
+    assert_eq!(p1.fold().to_string(), "((((a + 1) / 0) + 3) * 4)");
+    assert_eq!(p2.fold().to_string(), "(((a + 1) / 3) * 4)");
+    let standard = parse_expr(&tokens, &OpTable::standard()).unwrap();
+    assert_eq!(standard.fold().to_string(), "((a + (1 / 0)) + 12)");
+    assert_eq!(
+        parse_expr(&tokenize("--a").unwrap(), &OpTable::standard())
+            .unwrap()
+            .tree(),
+        "neg\n  neg\n    a\n"
+    );
+}
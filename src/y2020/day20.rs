@@ -2,7 +2,6 @@
 
 use crate::GroupBlankLine;
 
-use anyhow::private::kind::TraitKind;
 use counter::Counter;
 use itertools::Itertools;
 use num_integer::Roots;
@@ -0,0 +1,386 @@
+//! The handheld game console from 2020 day 8.
+//!
+//! Programs are lists of `acc`, `jmp` and `nop` instructions with a signed argument. A
+//! program terminates by jumping to the instruction right after its last one. Executed
+//! instructions are tracked in a bitset, so a run stops the first time an instruction is
+//! about to execute again, which for this machine means it loops forever.
+
+use crate::cfg::FlowInstr;
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    Acc,
+    Jmp,
+    Nop,
+}
+
+impl Op {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Op::Acc => "acc",
+            Op::Jmp => "jmp",
+            Op::Nop => "nop",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownOp(String),
+    BadArgument(String),
+    MissingArgument,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Line number of the bad instruction, starting at 1.
+    pub line: usize,
+    pub kind: ErrorKind,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handheld program error on line {}: ", self.line)?;
+        match &self.kind {
+            ErrorKind::UnknownOp(op) => write!(f, "unknown op '{}'", op),
+            ErrorKind::BadArgument(arg) => write!(f, "bad argument '{}'", arg),
+            ErrorKind::MissingArgument => write!(f, "missing argument"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl FromStr for Op {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "acc" => Op::Acc,
+            "jmp" => Op::Jmp,
+            "nop" => Op::Nop,
+            _ => return Err(ErrorKind::UnknownOp(s.to_string())),
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Instr {
+    pub op: Op,
+    pub arg: i32,
+}
+
+impl Instr {
+    /// The address of the next instruction, if this one is at `ip`.
+    pub fn next_ip(&self, ip: usize) -> isize {
+        match self.op {
+            Op::Jmp => ip as isize + self.arg as isize,
+            Op::Acc | Op::Nop => ip as isize + 1,
+        }
+    }
+
+    /// Swaps `jmp` and `nop`, or `None` for `acc`.
+    pub fn flipped(&self) -> Option<Self> {
+        let op = match self.op {
+            Op::Acc => return None,
+            Op::Jmp => Op::Nop,
+            Op::Nop => Op::Jmp,
+        };
+        Some(Self { op, arg: self.arg })
+    }
+}
+
+impl FromStr for Instr {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_ascii_whitespace();
+        let op = words.next().unwrap_or("").parse()?;
+        let arg = words.next().ok_or(ErrorKind::MissingArgument)?;
+        let arg = arg
+            .parse()
+            .map_err(|_| ErrorKind::BadArgument(arg.to_string()))?;
+        Ok(Self { op, arg })
+    }
+}
+
+impl Display for Instr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {:+}", self.op.mnemonic(), self.arg)
+    }
+}
+
+impl FlowInstr for Instr {
+    fn successors(&self, addr: usize) -> Vec<isize> {
+        vec![self.next_ip(addr)]
+    }
+}
+
+pub type Program = Vec<Instr>;
+
+pub fn parse_program<L: IntoIterator<Item = S>, S: AsRef<str>>(
+    lines: L,
+) -> Result<Program, ParseError> {
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(n, line)| {
+            line.as_ref()
+                .parse()
+                .map_err(|kind| ParseError { line: n + 1, kind })
+        })
+        .collect()
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RunResult {
+    /// Jumped to the end of the program.
+    Terminated { acc: i32 },
+    /// Was about to execute the instruction at `ip` a second time.
+    Looped { ip: usize, acc: i32 },
+    /// Jumped outside the program, to somewhere else than its end.
+    OutOfBounds { ip: isize, acc: i32 },
+}
+
+impl RunResult {
+    pub fn acc(&self) -> i32 {
+        match *self {
+            RunResult::Terminated { acc }
+            | RunResult::Looped { acc, .. }
+            | RunResult::OutOfBounds { acc, .. } => acc,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Bitset(Vec<u64>);
+
+impl Bitset {
+    fn with_len(len: usize) -> Self {
+        Self(vec![0; len.div_ceil(64)])
+    }
+
+    /// Sets bit `n`, returning `false` if it was already set.
+    fn insert(&mut self, n: usize) -> bool {
+        let (word, bit) = (n / 64, 1 << (n % 64));
+        let was_set = self.0[word] & bit != 0;
+        self.0[word] |= bit;
+        !was_set
+    }
+
+    fn contains(&self, n: usize) -> bool {
+        self.0[n / 64] & (1 << (n % 64)) != 0
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Cpu<'a> {
+    prog: &'a [Instr],
+    acc: i32,
+    ip: usize,
+    visited: Bitset,
+    /// Instruction executed as flipped, see `Cpu::with_patch()`.
+    patch: Option<usize>,
+}
+
+impl<'a> Cpu<'a> {
+    pub fn new(prog: &'a [Instr]) -> Self {
+        Self {
+            prog,
+            acc: 0,
+            ip: 0,
+            visited: Bitset::with_len(prog.len()),
+            patch: None,
+        }
+    }
+
+    /// A CPU that executes the instruction at `patch` with `jmp` and `nop` swapped, without
+    /// copying the program.
+    pub fn with_patch(prog: &'a [Instr], patch: usize) -> Self {
+        Self {
+            patch: Some(patch),
+            ..Self::new(prog)
+        }
+    }
+
+    pub fn acc(&self) -> i32 {
+        self.acc
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    /// Checks if the instruction at `ip` has been executed.
+    pub fn visited(&self, ip: usize) -> bool {
+        ip < self.prog.len() && self.visited.contains(ip)
+    }
+
+    fn instr(&self, ip: usize) -> Instr {
+        let instr = self.prog[ip];
+        match self.patch {
+            Some(p) if p == ip => instr.flipped().unwrap_or(instr),
+            _ => instr,
+        }
+    }
+
+    pub fn run(&mut self) -> RunResult {
+        loop {
+            if self.ip == self.prog.len() {
+                return RunResult::Terminated { acc: self.acc };
+            }
+            if !self.visited.insert(self.ip) {
+                return RunResult::Looped {
+                    ip: self.ip,
+                    acc: self.acc,
+                };
+            }
+            let instr = self.instr(self.ip);
+            if instr.op == Op::Acc {
+                self.acc += instr.arg;
+            }
+            let next = instr.next_ip(self.ip);
+            if next < 0 || next as usize > self.prog.len() {
+                return RunResult::OutOfBounds {
+                    ip: next,
+                    acc: self.acc,
+                };
+            }
+            self.ip = next as usize;
+        }
+    }
+}
+
+/// Instructions that lead to the end of the program when executed unmodified. Found by
+/// walking the reversed control flow graph back from the end.
+pub fn reaches_end(prog: &[Instr]) -> Vec<bool> {
+    let mut preds = vec![vec![]; prog.len() + 1];
+    for (ip, instr) in prog.iter().enumerate() {
+        let next = instr.next_ip(ip);
+        if next >= 0 && next as usize <= prog.len() {
+            preds[next as usize].push(ip);
+        }
+    }
+    let mut reaches = vec![false; prog.len() + 1];
+    reaches[prog.len()] = true;
+    let mut todo = vec![prog.len()];
+    while let Some(ip) = todo.pop() {
+        for &p in &preds[ip] {
+            if !reaches[p] {
+                reaches[p] = true;
+                todo.push(p);
+            }
+        }
+    }
+    reaches.truncate(prog.len());
+    reaches
+}
+
+/// Finds the single `jmp` or `nop` that makes the program terminate when swapped, and the
+/// final accumulator. Only instructions that run before the loop can matter, and swapping
+/// one of them only helps if its new target already leads to the end, so this is linear in
+/// the size of the program.
+pub fn repair(prog: &[Instr]) -> Option<(usize, i32)> {
+    let mut cpu = Cpu::new(prog);
+    if let RunResult::Terminated { .. } = cpu.run() {
+        return None;
+    }
+    let reaches = reaches_end(prog);
+    let patch = (0..prog.len()).filter(|&ip| cpu.visited(ip)).find(|&ip| {
+        prog[ip].flipped().is_some_and(|instr| {
+            let next = instr.next_ip(ip);
+            next >= 0 && (next as usize == prog.len() || reaches.get(next as usize) == Some(&true))
+        })
+    })?;
+    match Cpu::with_patch(prog, patch).run() {
+        RunResult::Terminated { acc } => Some((patch, acc)),
+        _ => None,
+    }
+}
+
+#[test]
+fn console() {
+    let prog = parse_program(vec![
+        "nop +0", "acc +1", "jmp +4", "acc +3", "jmp -3", "acc -99", "acc +1", "jmp -4", "acc +6",
+    ])
+    .unwrap();
+    assert_eq!(Cpu::new(&prog).run(), RunResult::Looped { ip: 1, acc: 5 });
+    assert_eq!(
+        reaches_end(&prog),
+        [false, false, false, false, false, false, false, false, true]
+    );
+    assert_eq!(repair(&prog), Some((7, 8)));
+    assert_eq!(prog[7].to_string(), "jmp -4");
+
+    let prog = parse_program(vec!["acc +2", "jmp -2"]).unwrap();
+    assert_eq!(
+        Cpu::new(&prog).run(),
+        RunResult::OutOfBounds { ip: -1, acc: 2 }
+    );
+    assert_eq!(
+        Cpu::with_patch(&prog, 1).run(),
+        RunResult::Terminated { acc: 2 }
+    );
+    assert_eq!(repair(&prog), Some((1, 2)));
+    assert_eq!(repair(&parse_program(vec!["nop +0"]).unwrap()), None);
+
+    let err = parse_program(vec!["nop +0", "hcf +1"]).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::UnknownOp("hcf".to_string()));
+    assert_eq!(
+        err.to_string(),
+        "Handheld program error on line 2: unknown op 'hcf'"
+    );
+    assert_eq!("acc".parse::<Instr>(), Err(ErrorKind::MissingArgument));
+    assert_eq!(
+        "acc 1x".parse::<Instr>(),
+        Err(ErrorKind::BadArgument("1x".to_string()))
+    );
+}
+
+#[test]
+fn control_flow() {
+    use crate::cfg::{Cfg, Target};
+
+    let prog = parse_program(vec![
+        "nop +0", "acc +1", "jmp +4", "acc +3", "jmp -3", "acc -99", "acc +1", "jmp -4", "acc +6",
+    ])
+    .unwrap();
+    let cfg = Cfg::build(&prog);
+    let starts: Vec<_> = cfg.blocks.iter().map(|b| b.start).collect();
+    assert_eq!(starts, [0, 1, 3, 5, 6, 8]);
+    assert_eq!(cfg.block_of(7), Some(4));
+    assert_eq!(cfg.blocks[5].succs, [Target::Exit]);
+    assert_eq!(cfg.reachable(), [true, true, true, false, true, false]);
+    assert_eq!(cfg.back_edges(), [(2, 1)]);
+    assert_eq!(cfg.loops(), [(1, vec![1, 2, 4])]);
+
+    let (patch, _) = repair(&prog).unwrap();
+    assert_eq!(
+        cfg.to_dot(&prog, &[patch]),
+        r##"digraph cfg {
+    node [shape=box, fontname="monospace"];
+    b0 [label="0: nop +0\l"];
+    b0 -> b1;
+    b1 [label="1: acc +1\l2: jmp +4\l"];
+    b1 -> b4;
+    b2 [label="3: acc +3\l4: jmp -3\l"];
+    b2 -> b1 [color=red];
+    b3 [label="5: acc -99\l"];
+    b3 -> b4;
+    b4 [label="6: acc +1\l* 7: jmp -4\l", style=filled, fillcolor="#ffcccc"];
+    b4 -> b2;
+    b5 [label="8: acc +6\l"];
+    b5 -> exit;
+    exit [shape=doublecircle, label="end"];
+}
+"##
+    );
+
+    let prog = parse_program(vec!["jmp +2", "jmp -5", "acc +1"]).unwrap();
+    let dot = Cfg::build(&prog).to_dot(&prog, &[]);
+    assert!(dot.contains("b1 -> oob_4;\n"));
+    assert!(dot.contains("oob_4 [shape=octagon, label=\"ip -4\"];"));
+}
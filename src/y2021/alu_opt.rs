@@ -0,0 +1,411 @@
+//! Value range analysis and optimization of day 24 ALU programs.
+//!
+//! `analyze()` tracks the interval each register can be in before every instruction,
+//! given the range of each input value. Constant registers are the special case of a
+//! single value interval, so this also does constant propagation. `optimize()` uses the
+//! ranges to drop instructions that can't change their register, to replace operations
+//! with a constant result by a simpler one, and then removes stores that are never read.
+//!
+//! Division or modulo by zero is treated as if it can't happen, so an optimized program
+//! may run where the original would have crashed.
+
+use super::day24::{Instruction, OpCode, Reg};
+
+use either::Either;
+
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+
+const REGS: [Reg; 4] = [Reg::W, Reg::X, Reg::Y, Reg::Z];
+
+/// An inclusive range of register values.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) struct Interval {
+    pub(super) min: i64,
+    pub(super) max: i64,
+}
+
+impl Interval {
+    pub(super) fn new(min: i64, max: i64) -> Self {
+        debug_assert!(min <= max);
+        Self { min, max }
+    }
+
+    pub(super) fn constant(c: i64) -> Self {
+        Self::new(c, c)
+    }
+
+    pub(super) fn full() -> Self {
+        Self::new(i64::MIN, i64::MAX)
+    }
+
+    /// The range of a model number digit.
+    pub(super) fn digit() -> Self {
+        Self::new(1, 9)
+    }
+
+    pub(super) fn as_constant(&self) -> Option<i64> {
+        (self.min == self.max).then_some(self.min)
+    }
+
+    pub(super) fn contains(&self, val: i64) -> bool {
+        self.min <= val && val <= self.max
+    }
+
+    /// The smallest interval containing all the values, or the full range if any of them
+    /// overflowed.
+    fn hull(vals: &[Option<i64>]) -> Self {
+        let mut vals = vals.iter().copied();
+        let first = vals.next().flatten();
+        vals.fold(first.map(Self::constant), |hull, val| {
+            let (hull, val) = (hull?, val?);
+            Some(Self::new(hull.min.min(val), hull.max.max(val)))
+        })
+        .unwrap_or_else(Self::full)
+    }
+
+    fn add(self, b: Self) -> Self {
+        Self::hull(&[self.min.checked_add(b.min), self.max.checked_add(b.max)])
+    }
+
+    fn mul(self, b: Self) -> Self {
+        Self::hull(&[
+            self.min.checked_mul(b.min),
+            self.min.checked_mul(b.max),
+            self.max.checked_mul(b.min),
+            self.max.checked_mul(b.max),
+        ])
+    }
+
+    /// `b` without zero at its ends, or `None` if it is only zero.
+    fn nonzero(self) -> Option<Self> {
+        match (self.min, self.max) {
+            (0, 0) => None,
+            (0, max) => Some(Self::new(1, max)),
+            (min, 0) => Some(Self::new(min, -1)),
+            _ => Some(self),
+        }
+    }
+
+    /// The largest absolute value.
+    fn magnitude(self) -> i64 {
+        self.min.saturating_abs().max(self.max.saturating_abs())
+    }
+
+    fn div(self, b: Self) -> Self {
+        let b = match b.nonzero() {
+            Some(b) => b,
+            None => return Self::full(),
+        };
+        if b.contains(0) {
+            // Division by -1 or 1 keeps the magnitude.
+            let m = self.magnitude();
+            return Self::new(-m, m);
+        }
+        // Truncating division is monotone in both arguments when the sign of the divisor
+        // is fixed, so the extremes are at the corners.
+        Self::hull(&[
+            self.min.checked_div(b.min),
+            self.min.checked_div(b.max),
+            self.max.checked_div(b.min),
+            self.max.checked_div(b.max),
+        ])
+    }
+
+    fn rem(self, b: Self) -> Self {
+        let b = match b.nonzero() {
+            Some(b) => b,
+            None => return Self::full(),
+        };
+        if let Some(c) = b.as_constant() {
+            // Exact if the dividend doesn't wrap around a multiple of the divisor.
+            let q = (self.min.checked_div(c), self.max.checked_div(c));
+            if q.0.is_some() && q.0 == q.1 && (self.min >= 0 || self.max <= 0) {
+                return Self::new(self.min % c, self.max % c);
+            }
+        }
+        // The result has the sign of the dividend and is smaller than the divisor.
+        let m = b.magnitude() - 1;
+        Self::new(self.min.max(-m).min(0), self.max.min(m).max(0))
+    }
+
+    fn eql(self, b: Self) -> Self {
+        if self.as_constant().is_some() && self.as_constant() == b.as_constant() {
+            Self::constant(1)
+        } else if self.max < b.min || b.max < self.min {
+            Self::constant(0)
+        } else {
+            Self::new(0, 1)
+        }
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.as_constant() {
+            Some(c) => write!(f, "{}", c),
+            None => write!(f, "{}..={}", self.min, self.max),
+        }
+    }
+}
+
+pub(super) type Ranges = [Interval; 4];
+
+/// Register ranges before each instruction of a program.
+#[derive(Clone, Debug)]
+pub(super) struct Analysis {
+    pub(super) before: Vec<Ranges>,
+    /// Ranges when the program ends.
+    pub(super) end: Ranges,
+}
+
+impl Analysis {
+    /// The ranges after the instruction at `n`.
+    pub(super) fn after(&self, n: usize) -> &Ranges {
+        self.before.get(n + 1).unwrap_or(&self.end)
+    }
+
+    /// The range of the register written by the instruction at `n`.
+    pub(super) fn result(&self, program: &[Instruction], n: usize) -> Interval {
+        self.after(n)[program[n].a as usize]
+    }
+}
+
+/// Analyzes `program` starting with all registers at zero. `inputs` has the range of each
+/// input value in order. Inputs past its end can have any value.
+pub(super) fn analyze(program: &[Instruction], inputs: &[Interval]) -> Analysis {
+    analyze_from(program, [Interval::constant(0); 4], inputs)
+}
+
+/// Same as `analyze()`, starting with the registers in `start`.
+pub(super) fn analyze_from(
+    program: &[Instruction],
+    start: Ranges,
+    inputs: &[Interval],
+) -> Analysis {
+    let mut regs = start;
+    let mut inputs = inputs.iter();
+    let mut before = Vec::with_capacity(program.len());
+    for instr in program {
+        before.push(regs);
+        let a = regs[instr.a as usize];
+        let b = match instr.b {
+            Either::Left(reg) => regs[reg as usize],
+            Either::Right(imm) => Interval::constant(imm as i64),
+        };
+        regs[instr.a as usize] = match instr.op {
+            OpCode::Add => a.add(b),
+            OpCode::Mul => a.mul(b),
+            OpCode::Div => a.div(b),
+            OpCode::Mod => a.rem(b),
+            OpCode::Eql => a.eql(b),
+            OpCode::Inp => inputs.next().copied().unwrap_or_else(Interval::full),
+        };
+    }
+    Analysis { before, end: regs }
+}
+
+fn imm(op: OpCode, a: Reg, val: i64) -> Option<Instruction> {
+    let val = i32::try_from(val).ok()?;
+    Some(Instruction {
+        op,
+        a,
+        b: Either::Right(val),
+    })
+}
+
+/// Rewrites a single instruction using the ranges before and after it. Returns `None` if
+/// the instruction can be removed.
+fn simplify(instr: &Instruction, before: &Ranges, after: &Ranges) -> Option<Instruction> {
+    if instr.op == OpCode::Inp {
+        return Some(*instr);
+    }
+    let mut instr = *instr;
+    if let Either::Left(reg) = instr.b {
+        if let Some(c) = before[reg as usize].as_constant() {
+            instr = imm(instr.op, instr.a, c).unwrap_or(instr);
+        }
+    }
+    let prev = before[instr.a as usize];
+    let result = after[instr.a as usize];
+    let clear = imm(OpCode::Mul, instr.a, 0);
+    match (prev.as_constant(), result.as_constant()) {
+        (Some(p), Some(c)) if p == c => return None,
+        (_, Some(0)) => return clear,
+        (Some(p), Some(c)) => return imm(OpCode::Add, instr.a, c - p).or(Some(instr)),
+        _ => {}
+    }
+    match (instr.op, instr.b) {
+        (OpCode::Add, Either::Right(0))
+        | (OpCode::Mul, Either::Right(1))
+        | (OpCode::Div, Either::Right(1)) => None,
+        (OpCode::Mod, Either::Right(c)) if prev.min >= 0 && prev.max < c as i64 => None,
+        _ => Some(instr),
+    }
+}
+
+/// The registers whose value is used later, before each instruction and at the end.
+/// Only the registers in `outputs` are used after the program ends. Instructions that
+/// write a register that isn't live don't use their inputs either.
+pub(super) fn liveness(program: &[Instruction], outputs: &[Reg]) -> Vec<[bool; 4]> {
+    let mut live = [false; 4];
+    for &reg in outputs {
+        live[reg as usize] = true;
+    }
+    let mut before = vec![live];
+    for instr in program.iter().rev() {
+        let a = instr.a as usize;
+        if instr.op == OpCode::Inp || live[a] {
+            // Input and `mul a 0` don't depend on the old value.
+            live[a] = !matches!(
+                (instr.op, instr.b),
+                (OpCode::Inp, _) | (OpCode::Mul, Either::Right(0))
+            );
+            if let Either::Left(reg) = instr.b {
+                live[reg as usize] = true;
+            }
+        }
+        before.push(live);
+    }
+    before.reverse();
+    before
+}
+
+/// Removes instructions whose result is overwritten or never used. Input instructions
+/// are always kept, since they consume input.
+fn remove_dead_stores(program: &[Instruction], outputs: &[Reg]) -> Vec<Instruction> {
+    let live = liveness(program, outputs);
+    program
+        .iter()
+        .zip(&live[1..])
+        .filter(|(instr, after)| instr.op == OpCode::Inp || after[instr.a as usize])
+        .map(|(instr, _)| *instr)
+        .collect()
+}
+
+/// Optimizes `program` for the given input ranges, keeping the final value of the
+/// `outputs` registers. Repeats until nothing changes, since removing a store can make
+/// more registers constant.
+pub(super) fn optimize(
+    program: &[Instruction],
+    inputs: &[Interval],
+    outputs: &[Reg],
+) -> Vec<Instruction> {
+    let mut program = program.to_vec();
+    loop {
+        let analysis = analyze(&program, inputs);
+        let simplified: Vec<_> = program
+            .iter()
+            .enumerate()
+            .filter_map(|(n, instr)| simplify(instr, &analysis.before[n], analysis.after(n)))
+            .collect();
+        let optimized = remove_dead_stores(&simplified, outputs);
+        if optimized == program {
+            return program;
+        }
+        program = optimized;
+    }
+}
+
+/// Formats the program with the ranges of the registers after each instruction.
+pub(super) fn annotate(program: &[Instruction], analysis: &Analysis) -> String {
+    let mut out = String::new();
+    for (n, instr) in program.iter().enumerate() {
+        let after = analysis.after(n);
+        let ranges: Vec<_> = REGS
+            .iter()
+            .map(|&reg| format!("{}={}", reg, after[reg as usize]))
+            .collect();
+        out += &format!("{:<12} ; {}\n", instr.to_string(), ranges.join(" "));
+    }
+    out
+}
+
+#[test]
+fn intervals() {
+    let i = Interval::new;
+    assert_eq!(i(-3, 5).mul(i(-2, 4)), i(-12, 20));
+    assert_eq!(i(-7, 30).div(Interval::constant(-2)), i(-15, 3));
+    assert_eq!(i(-7, 30).div(i(-1, 1)), i(-30, 30));
+    assert_eq!(i(0, 20).rem(Interval::constant(26)), i(0, 20));
+    assert_eq!(i(0, 100).rem(Interval::constant(26)), i(0, 25));
+    assert_eq!(i(60, 70).rem(Interval::constant(-26)), i(8, 18));
+    assert_eq!(i(-100, 3).rem(i(5, 8)), i(-7, 3));
+    assert_eq!(i(i64::MIN, -5).rem(Interval::constant(-1)), i(0, 0));
+    assert_eq!(i(10, 20).eql(Interval::digit()), Interval::constant(0));
+    assert_eq!(
+        Interval::full().add(Interval::constant(1)),
+        Interval::full()
+    );
+}
+
+#[test]
+fn optimize_real_data() {
+    use super::day24::{load_input, parse, Alu};
+
+    let program = load_input(crate::load_strings("data/2021/day24.txt"));
+    let digits = [Interval::digit(); 14];
+    let analysis = analyze(&program, &digits);
+    // The first block's test can never pass, since z starts at 0.
+    assert_eq!(program[6].to_string(), "eql x w");
+    assert_eq!(analysis.result(&program, 6), Interval::constant(0));
+    // But it can in the fourth block, which pops a digit.
+    assert_eq!(analysis.result(&program, 3 * 18 + 6), Interval::new(0, 1));
+    assert_eq!(analysis.end[Reg::Z as usize].min, 0);
+    // After a known prefix, the test is constant for each digit.
+    let mut alu = Alu {
+        program: &program[..3 * 18],
+        regs: [0; 4],
+    };
+    assert!(alu.run_with_input(vec![5, 1, 9].into_iter()));
+    let start = alu.regs.map(Interval::constant);
+    let block = &program[3 * 18..4 * 18];
+    let tests: Vec<_> = (1..=9)
+        .map(|d| analyze_from(block, start, &[Interval::constant(d)]).result(block, 6))
+        .collect();
+    assert!(tests.iter().all(|t| t.as_constant().is_some()));
+    assert_eq!(tests[7], Interval::constant(1));
+    assert_eq!(
+        tests
+            .iter()
+            .filter(|&&t| t == Interval::constant(1))
+            .count(),
+        1
+    );
+
+    let optimized = optimize(&program, &digits, &[Reg::Z]);
+    let first_block: Vec<_> = optimized.iter().take(4).map(|i| i.to_string()).collect();
+    assert_eq!(first_block, ["inp w", "add y w", "add y 6", "add z y"]);
+    assert_eq!(optimized.len(), 164);
+    assert_eq!(optimized.iter().filter(|i| i.op == OpCode::Inp).count(), 14);
+
+    let mut full = Alu {
+        program: &program,
+        regs: [0; 4],
+    };
+    let mut opt = Alu {
+        program: &optimized,
+        regs: [0; 4],
+    };
+    for n in 0..1000u64 {
+        let model = n.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        let digits = || (0..14).map(move |i| (model >> (4 * i) & 7) as i32 + 1);
+        assert!(full.run_with_input(digits()));
+        assert!(opt.run_with_input(digits()));
+        assert_eq!(opt.regs[Reg::Z as usize], full.regs[Reg::Z as usize]);
+    }
+
+    // Dead code of an other program.
+    let program = load_input(vec!["inp x", "mul y 0", "add y x", "add x 1", "mul y 2"]);
+    let optimized = optimize(&program, &[Interval::full()], &[Reg::Y]);
+    assert_eq!(optimized, load_input(vec!["inp x", "add y x", "mul y 2"]));
+    let analysis = analyze(&optimized, &[Interval::new(-3, 3)]);
+    assert_eq!(
+        annotate(&optimized, &analysis),
+        "inp x        ; w=0 x=-3..=3 y=0 z=0
+add y x      ; w=0 x=-3..=3 y=-3..=3 z=0
+mul y 2      ; w=0 x=-3..=3 y=-6..=6 z=0
+"
+    );
+    assert_eq!(parse("mul y 2"), optimized[2]);
+}
@@ -0,0 +1,208 @@
+//! Digit by digit search for inputs that make an ALU program end with `z == 0`.
+//!
+//! The program is split into chunks at each `inp`. The search tries each input symbol for
+//! the next chunk in order and recurses, so the first answer found is the largest or the
+//! smallest in digit order. States that have failed before are remembered by chunk and
+//! the registers that are still live, which for the day 24 programs is just `z`. Before a
+//! state is expanded, the range analysis checks that `z` can still end up at 0.
+//!
+//! Instructions that the ALU can't execute, such as a division by zero, end the branch of
+//! the search they are in, as if that input was rejected.
+
+use super::alu_opt::{analyze_from, liveness, Interval};
+use super::day24::{Alu, Instruction, OpCode, Reg};
+
+use std::collections::HashSet;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) enum Order {
+    MaxFirst,
+    MinFirst,
+}
+
+type Regs = [i64; 4];
+
+struct Search<'a> {
+    program: &'a [Instruction],
+    /// Start of each chunk, at an `inp`.
+    starts: Vec<usize>,
+    /// Live registers at the start of each chunk.
+    live: Vec<[bool; 4]>,
+    /// Input symbols in the order they are tried.
+    alphabet: Vec<i32>,
+    range: Interval,
+    failed: HashSet<(usize, Regs)>,
+    input: Vec<i32>,
+}
+
+impl<'a> Search<'a> {
+    /// Returns `None` if `alphabet` is empty.
+    fn new(program: &'a [Instruction], alphabet: &[i32], order: Order) -> Option<Self> {
+        let starts: Vec<_> = program
+            .iter()
+            .enumerate()
+            .filter(|(_, instr)| instr.op == OpCode::Inp)
+            .map(|(n, _)| n)
+            .collect();
+        let live_before = liveness(program, &[Reg::Z]);
+        let live = starts.iter().map(|&s| live_before[s]).collect();
+        let mut alphabet = alphabet.to_vec();
+        alphabet.sort_unstable();
+        alphabet.dedup();
+        let range = Interval::new(*alphabet.first()? as i64, *alphabet.last()? as i64);
+        if order == Order::MaxFirst {
+            alphabet.reverse();
+        }
+        Some(Self {
+            program,
+            starts,
+            live,
+            alphabet,
+            range,
+            failed: HashSet::new(),
+            input: vec![],
+        })
+    }
+
+    fn chunk(&self, n: usize) -> &'a [Instruction] {
+        let end = self
+            .starts
+            .get(n + 1)
+            .copied()
+            .unwrap_or(self.program.len());
+        &self.program[self.starts[n]..end]
+    }
+
+    /// Checks if the rest of the program, from the start of chunk `n`, can end with
+    /// `z == 0`.
+    fn can_succeed(&self, n: usize, regs: &Regs) -> bool {
+        let rest = &self.program[self.starts[n]..];
+        let inputs = self.starts.len() - n;
+        let analysis = analyze_from(
+            rest,
+            regs.map(Interval::constant),
+            &vec![self.range; inputs],
+        );
+        analysis.end[Reg::Z as usize].contains(0)
+    }
+
+    /// Searches for input for chunk `n` onwards, pushing it to `self.input`.
+    fn find(&mut self, n: usize, mut regs: Regs) -> bool {
+        if n == self.starts.len() {
+            return regs[Reg::Z as usize] == 0;
+        }
+        for (reg, live) in regs.iter_mut().zip(&self.live[n]) {
+            if !live {
+                *reg = 0;
+            }
+        }
+        if self.failed.contains(&(n, regs)) {
+            return false;
+        }
+        if self.can_succeed(n, &regs) {
+            let mut alu = Alu {
+                program: self.chunk(n),
+                regs,
+            };
+            for i in 0..self.alphabet.len() {
+                let symbol = self.alphabet[i];
+                alu.regs = regs;
+                if !alu.resume(std::iter::once(symbol)) {
+                    continue;
+                }
+                self.input.push(symbol);
+                if self.find(n + 1, alu.regs) {
+                    return true;
+                }
+                self.input.pop();
+            }
+        }
+        self.failed.insert((n, regs));
+        false
+    }
+}
+
+/// Finds the first input, in the given order of `alphabet`, for which `program` ends with
+/// `z == 0`. The input has one symbol per `inp` instruction. Returns `None` if there is
+/// no such input, which includes an empty `alphabet`.
+pub(super) fn search(program: &[Instruction], alphabet: &[i32], order: Order) -> Option<Vec<i32>> {
+    let mut search = Search::new(program, alphabet, order)?;
+    // Instructions before the first input only need to run once.
+    let mut setup = Alu {
+        program: &program[..search.starts.first().copied().unwrap_or(program.len())],
+        regs: [0; 4],
+    };
+    if !setup.run_with_input(std::iter::empty()) {
+        return None;
+    }
+    search.find(0, setup.regs).then_some(search.input)
+}
+
+#[test]
+fn search_real_data() {
+    use super::day24::load_input;
+
+    let program = load_input(crate::load_strings("data/2021/day24.txt"));
+    let digits: Vec<_> = (1..=9).collect();
+    let number = |input: Vec<i32>| input.iter().map(|d| d.to_string()).collect::<String>();
+    let largest = search(&program, &digits, Order::MaxFirst).unwrap();
+    assert_eq!(number(largest), "51983999947999");
+    let smallest = search(&program, &digits, Order::MinFirst).unwrap();
+    assert_eq!(number(smallest), "11211791111365");
+}
+
+#[test]
+fn search_alphabet() {
+    use super::day24::load_input;
+
+    // 3a + b == 7, after a setup chunk with no input.
+    let program = load_input(vec![
+        "add z -7", "inp w", "mul w 3", "add z w", "inp w", "add z w",
+    ]);
+    let alphabet: Vec<_> = (-2..=5).collect();
+    assert_eq!(
+        search(&program, &alphabet, Order::MaxFirst),
+        Some(vec![3, -2])
+    );
+    assert_eq!(
+        search(&program, &alphabet, Order::MinFirst),
+        Some(vec![1, 4])
+    );
+    assert_eq!(search(&program, &[0, 1], Order::MaxFirst), None);
+    assert_eq!(
+        search(&program[1..], &[0], Order::MinFirst),
+        Some(vec![0, 0])
+    );
+    assert_eq!(search(&program, &[], Order::MaxFirst), None);
+}
+
+#[test]
+fn search_invalid_ops() {
+    use super::day24::load_input;
+
+    // Only 2 gets past the division and the modulo, 0 divides by zero and -1 is a
+    // negative dividend.
+    let program = load_input(vec![
+        "inp w", "add x 6", "div x w", "mod w 5", "add z x", "add z -3", "mul z w",
+    ]);
+    let alphabet: Vec<_> = (-1..=2).collect();
+    assert_eq!(search(&program, &alphabet, Order::MinFirst), Some(vec![2]));
+    assert_eq!(search(&program, &[0, -1], Order::MaxFirst), None);
+
+    // The multiplication overflows, and so does the setup chunk in the second program.
+    let program = load_input(vec![
+        "inp z",
+        "mul z 2147483647",
+        "mul z z",
+        "mul z z",
+        "mul z 0",
+    ]);
+    assert_eq!(
+        search(&program, &[0, 1 << 30], Order::MaxFirst),
+        Some(vec![0])
+    );
+    let program = load_input(vec!["add z 2147483647", "mul z z", "mul z z", "mul z z"]);
+    assert_eq!(search(&program, &[1], Order::MaxFirst), None);
+    let program = load_input(vec!["mod z 0", "inp w"]);
+    assert_eq!(search(&program, &[1], Order::MaxFirst), None);
+}
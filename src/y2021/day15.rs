@@ -15,7 +15,8 @@ fn part1(input: &Input) -> usize {
 
 // https://en.wikipedia.org/wiki/A*_search_algorithm
 fn a_star(grid: &Input) -> usize {
-    let h = |n: Coord| (grid.width() - n.x).abs() + (grid.height() - n.y).abs();
+    let corner = Coord::new(grid.width(), grid.height());
+    let h = |n: Coord| n.manhattan(corner);
     let bottom_right = Coord {
         x: grid.width() - 1,
         y: grid.height() - 1,
@@ -74,23 +75,14 @@ fn a_star(grid: &Input) -> usize {
 }
 
 fn part2(input: &Input) -> usize {
-    let mut x5 = Vec::with_capacity((input.width() * input.height() * 25) as usize);
-    for row in 0..5 {
-        for input_row in input.row_slices() {
-            for col in 0..5 {
-                x5.extend(input_row.iter().map(|&c| {
-                    let c = c + row + col;
-                    if c > 9 {
-                        c - 9
-                    } else {
-                        c
-                    }
-                }));
-            }
+    let x5 = input.tile(5, 5, |copy, &c| {
+        let c = c + (copy.x + copy.y) as u8;
+        if c > 9 {
+            c - 9
+        } else {
+            c
         }
-    }
-
-    let x5 = Grid::new(x5, input.width() * 5, input.height() * 5);
+    });
     a_star(&x5)
 }
 
@@ -1,7 +1,7 @@
+use crate::grid::Grid;
 use itertools::Itertools;
 use std::fmt::{Debug, Formatter};
 
-type LineType = Vec<u8>;
 type Input = (Vec<u8>, Image);
 
 fn load_input<L: IntoIterator<Item = S>, S: AsRef<str>>(line_source: L) -> Input {
@@ -15,38 +15,19 @@ fn load_input<L: IntoIterator<Item = S>, S: AsRef<str>>(line_source: L) -> Input
         .collect_vec();
     assert_eq!(alg.len(), 512);
     lines.next(); // blank
-    let mut img = lines.map(parse).collect_vec();
-    let inf_row = vec![0; img[0].len()];
-    img.insert(0, inf_row.clone());
-    img.insert(0, inf_row.clone());
-    img.push(inf_row.clone());
-    img.push(inf_row);
+    let img = Grid::from_lines(lines, |c| (c == '#') as u8);
     (alg, Image(img, 0))
 }
 
-fn parse<S: AsRef<str>>(s: S) -> LineType {
-    let mut x = 0;
-    let mut line = vec![0; 0];
-    line.extend(
-        s.as_ref()
-            .bytes()
-            .chain(".".bytes().cycle().take(102))
-            .map(|c| {
-                x = (x << 1 | (c == b'#') as u8) & 0b111;
-                x
-            }),
-    );
-    line
-}
-
+/// The lit pixels, and the value of all the pixels outside the grid.
 #[derive(Clone)]
-struct Image(Vec<Vec<u8>>, usize);
+struct Image(Grid<u8>, u8);
 
 impl Debug for Image {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for row in &self.0 {
+        for row in self.0.row_slices() {
             for c in row.iter() {
-                if *c & 0b010 != 0 {
+                if *c != 0 {
                     write!(f, "#")?;
                 } else {
                     write!(f, ".")?;
@@ -59,47 +40,28 @@ impl Debug for Image {
 }
 
 impl Image {
+    /// The image grows by one pixel on each side, as those pixels see some of the grid.
     fn enhance(&self, alg: &[u8]) -> Self {
-        let mut new = Vec::with_capacity(self.0.len() + 4);
-        let inf = self.1;
-        let inf_addr = inf << 6 | inf << 3 | inf;
-        let new_inf = if alg[inf_addr] == 0 { 0 } else { 0b111 };
-
-        let row_len = self.0[0].len();
-        new.push(vec![new_inf; row_len]);
-        new.push(vec![new_inf; row_len]);
-
-        for rows in self.0.as_slice().windows(3).map(|r| {
-            [
-                r[0].iter().copied(),
-                r[1].iter().copied(),
-                r[2].iter().copied(),
-            ]
-        }) {
-            let [r1, r2, r3] = rows;
-            let mut new_row = Vec::with_capacity(row_len);
-            let mut x = new_inf;
-            for addr in r1
-                .zip(r2)
-                .zip(r3)
-                .map(|((a, b), c)| (a as usize) << 6 | (b as usize) << 3 | c as usize)
-            {
-                x = (x << 1 | alg[addr]) & 0b111;
-                new_row.push(x);
+        let Image(img, inf) = self;
+        let padded = img.pad(2, *inf);
+        let width = img.width() + 2;
+        let rows = padded.row_slices().collect_vec();
+        let mut tiles = Vec::with_capacity((width * (img.height() + 2)) as usize);
+        for r in rows.windows(3) {
+            for x in 0..width as usize {
+                let addr = r
+                    .iter()
+                    .flat_map(|row| &row[x..x + 3])
+                    .fold(0, |addr, &p| addr << 1 | p as usize);
+                tiles.push(alg[addr]);
             }
-            new.push(new_row);
         }
-        new.push(vec![new_inf; row_len]);
-        new.push(vec![new_inf; row_len]);
-        Self(new, new_inf as usize)
+        let new_inf = alg[if *inf == 0 { 0 } else { 0b1_1111_1111 }];
+        Self(Grid::new(tiles, width, img.height() + 2), new_inf)
     }
 
     fn count_ones(&self) -> usize {
-        self.0
-            .iter()
-            .flat_map(|line| line.iter().copied())
-            .map(|p| (p >> 1 & 1) as usize)
-            .sum()
+        self.0.iter_tiles().filter(|(_, &p)| p != 0).count()
     }
 }
 
@@ -1,24 +1,25 @@
+use super::alu_search::{search, Order};
+use crate::cfg::FlowInstr;
+
 use either::Either;
 use itertools::Itertools;
-use std::borrow::Cow;
 
 use std::fmt::{Display, Formatter};
-use std::io::Write;
 use std::num::NonZeroI32;
 use std::str::FromStr;
 
-type Input = Vec<Instruction>;
+pub(super) type Input = Vec<Instruction>;
 
-fn load_input<L: IntoIterator<Item = S>, S: AsRef<str>>(line_source: L) -> Input {
+pub(super) fn load_input<L: IntoIterator<Item = S>, S: AsRef<str>>(line_source: L) -> Input {
     line_source.into_iter().map(parse).collect()
 }
 
-fn parse<S: AsRef<str>>(s: S) -> Instruction {
+pub(super) fn parse<S: AsRef<str>>(s: S) -> Instruction {
     s.as_ref().parse::<Instruction>().unwrap()
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
-enum Reg {
+pub(super) enum Reg {
     W = 0,
     X = 1,
     Y = 2,
@@ -38,7 +39,7 @@ impl Reg {
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum OpCode {
+pub(super) enum OpCode {
     Add,
     Mul,
     Div,
@@ -61,11 +62,11 @@ impl OpCode {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-struct Instruction {
-    op: OpCode,
-    a: Reg,
-    b: Either<Reg, i32>,
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) struct Instruction {
+    pub(super) op: OpCode,
+    pub(super) a: Reg,
+    pub(super) b: Either<Reg, i32>,
 }
 
 impl FromStr for Instruction {
@@ -94,29 +95,82 @@ impl FromStr for Instruction {
     }
 }
 
-struct Alu<'a> {
-    program: &'a [Instruction],
-    regs: [i64; 4],
+impl Display for Reg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::W => "w",
+            Self::X => "x",
+            Self::Y => "y",
+            Self::Z => "z",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let op = match self.op {
+            OpCode::Add => "add",
+            OpCode::Mul => "mul",
+            OpCode::Div => "div",
+            OpCode::Mod => "mod",
+            OpCode::Eql => "eql",
+            OpCode::Inp => return write!(f, "inp {}", self.a),
+        };
+        match self.b {
+            Either::Left(reg) => write!(f, "{} {} {}", op, self.a, reg),
+            Either::Right(imm) => write!(f, "{} {} {}", op, self.a, imm),
+        }
+    }
+}
+
+/// Straight line code, with a block for each input.
+impl FlowInstr for Instruction {
+    fn successors(&self, addr: usize) -> Vec<isize> {
+        vec![addr as isize + 1]
+    }
+
+    fn starts_block(&self) -> bool {
+        self.op == OpCode::Inp
+    }
+}
+
+pub(super) struct Alu<'a> {
+    pub(super) program: &'a [Instruction],
+    pub(super) regs: [i64; 4],
 }
 
 impl<'a> Alu<'a> {
-    fn run_with_input(&mut self, mut input: impl Iterator<Item = i32>) {
+    pub(super) fn run_with_input(&mut self, input: impl Iterator<Item = i32>) -> bool {
         self.regs = [0; 4];
+        self.resume(input)
+    }
+
+    /// Runs the program without resetting the registers. Stops and returns `false` at an
+    /// instruction the ALU can't execute: division by zero, `mod` with a negative dividend
+    /// or a divisor that isn't positive, overflow, or `inp` without input left.
+    pub(super) fn resume(&mut self, mut input: impl Iterator<Item = i32>) -> bool {
         for instr in self.program.iter() {
             let b = match instr.b {
                 Either::Left(reg) => self.regs[reg as usize],
                 Either::Right(imm) => imm as i64,
             };
             let a = &mut self.regs[instr.a as usize];
-            match instr.op {
-                OpCode::Add => *a += b,
-                OpCode::Mul => *a *= b,
-                OpCode::Div => *a /= b,
-                OpCode::Mod => *a %= b,
-                OpCode::Eql => *a = (*a == b) as i64,
-                OpCode::Inp => *a = input.next().unwrap() as i64,
+            let val = match instr.op {
+                OpCode::Add => a.checked_add(b),
+                OpCode::Mul => a.checked_mul(b),
+                OpCode::Div => a.checked_div(b),
+                OpCode::Mod if *a < 0 || b <= 0 => None,
+                OpCode::Mod => a.checked_rem(b),
+                OpCode::Eql => Some((*a == b) as i64),
+                OpCode::Inp => input.next().map(i64::from),
+            };
+            match val {
+                Some(val) => *a = val,
+                None => return false,
             }
         }
+        true
     }
 }
 
@@ -156,6 +210,20 @@ impl ModelNumber {
             }
         }
     }
+
+    fn from_digits(digits: &[i32]) -> Self {
+        let mut model = Self::new();
+        for (d, &digit) in model.0.iter_mut().zip(digits) {
+            d.0 = NonZeroI32::new(digit).unwrap();
+        }
+        model
+    }
+
+    fn value(&self) -> usize {
+        self.0
+            .iter()
+            .fold(0, |acc, d| acc * 10 + d.0.get() as usize)
+    }
 }
 
 impl Display for ModelNumber {
@@ -167,94 +235,198 @@ impl Display for ModelNumber {
     }
 }
 
-fn part1(program: &Input) -> usize {
-    let mut alu = Alu {
-        program,
-        regs: [0; 4],
-    };
-    let mut model = ModelNumber::default();
-    loop {
-        let iter = model.0.iter().map(|d| d.0.get());
-        let z = super::alu::alu(iter);
-        if z == 0 {
-            break;
-        }
-        model.decrement();
-        // println!("{}", model);
-    }
-
-    println!("Best model number {}", model);
-    0
-}
+/// The code for each digit, with the parameters that differ between the blocks as
+/// `DIV`, `ADD_X` and `ADD_Y`.
+const DIGIT_BLOCK: &str = "inp w
+mul x 0
+add x z
+mod x 26
+div z DIV
+add x ADD_X
+eql x w
+eql x 0
+mul y 0
+add y 25
+mul y x
+add y 1
+mul z y
+mul y 0
+add y w
+add y ADD_Y
+mul y x
+add z y";
 
-fn part2(_input: &Input) -> usize {
-    0
+/// Parameters of one digit block. Seen as a stack of base 26 digits, `z` gets `w + add_y`
+/// pushed if `w != top + add_x`. Blocks with `div` 26 pop the top first.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct DigitBlock {
+    div: i32,
+    add_x: i32,
+    add_y: i32,
 }
 
-#[test]
-fn create_func() {
-    let d = load_input(crate::load_strings(crate::data_file!()));
-    let mut out = std::fs::File::create("./src/y2021/alu_new.rs").unwrap();
-    writeln!(
-        out,
-        "pub fn alu(mut input: impl Iterator<Item=i32>) -> i64 {{"
-    )
-    .unwrap();
-    writeln!(out, "let mut w: i64= 0;").unwrap();
-    writeln!(out, "let mut x: i64= 0;").unwrap();
-    writeln!(out, "let mut y: i64= 0;").unwrap();
-    writeln!(out, "let mut z: i64= 0;").unwrap();
-    fn reg_str(reg: Reg) -> &'static str {
-        match reg {
-            Reg::W => "w",
-            Reg::X => "x",
-            Reg::Y => "y",
-            Reg::Z => "z",
-        }
+/// Splits the program into digit blocks, or `None` if it doesn't follow `DIGIT_BLOCK`.
+fn digit_blocks(program: &[Instruction]) -> Option<Vec<DigitBlock>> {
+    let template: Vec<_> = DIGIT_BLOCK.lines().collect();
+    if template.is_empty() || !program.len().is_multiple_of(template.len()) {
+        return None;
     }
-    for instr in d.iter() {
-        let b = match instr.b {
-            Either::Left(reg) => reg_str(reg).into(),
-            Either::Right(imm) => Cow::Owned(imm.to_string()),
-        };
-        let a = reg_str(instr.a);
-        match instr.op {
-            OpCode::Add => {
-                if b != "0" {
-                    writeln!(out, "{} += {};", a, b)
-                } else {
-                    continue;
-                }
-            }
-            OpCode::Mul => {
-                if b == "0" {
-                    writeln!(out, "\n{} = 0;", a)
-                } else {
-                    writeln!(out, "{} *= {};", a, b)
+    program
+        .chunks(template.len())
+        .map(|block| {
+            let mut params = DigitBlock {
+                div: 0,
+                add_x: 0,
+                add_y: 0,
+            };
+            for (instr, line) in block.iter().zip(&template) {
+                let (op_reg, arg) = line.rsplit_once(' ').unwrap_or((line, ""));
+                let param = match arg {
+                    "DIV" => Some(&mut params.div),
+                    "ADD_X" => Some(&mut params.add_x),
+                    "ADD_Y" => Some(&mut params.add_y),
+                    _ => None,
+                };
+                match param {
+                    Some(param) => {
+                        let expected = parse(format!("{} 0", op_reg));
+                        if (instr.op, instr.a) != (expected.op, expected.a) {
+                            return None;
+                        }
+                        *param = instr.b.right()?;
+                    }
+                    None => {
+                        let expected = parse(line);
+                        if (instr.op, instr.a, instr.b) != (expected.op, expected.a, expected.b) {
+                            return None;
+                        }
+                    }
                 }
             }
-            OpCode::Div => {
-                if b != "1" {
-                    writeln!(out, "{} /= {};", a, b)
-                } else {
-                    continue;
+            Some(params)
+        })
+        .collect()
+}
+
+/// Finds the largest and smallest accepted model numbers.
+///
+/// Blocks with `div` 1 always push, since their `add_x` is above 9. Each block with `div`
+/// 26 must then avoid pushing, so it is paired with the block that pushed the top of the
+/// stack: `digit[pop] == digit[push] + push.add_y + pop.add_x`. Every pair is solved on
+/// its own. Returns `None` if the program doesn't have this shape or can't be satisfied.
+fn solve(program: &[Instruction]) -> Option<(ModelNumber, ModelNumber)> {
+    let blocks = digit_blocks(program)?;
+    if blocks.len() != 14 {
+        return None;
+    }
+    let mut largest = [0; 14];
+    let mut smallest = [0; 14];
+    let mut stack = vec![];
+    for (n, block) in blocks.iter().enumerate() {
+        match block.div {
+            1 if block.add_x > 9 => stack.push(n),
+            26 => {
+                let push = stack.pop()?;
+                let diff = blocks[push].add_y + block.add_x;
+                if diff.abs() > 8 {
+                    return None;
                 }
+                largest[push] = 9.min(9 - diff);
+                largest[n] = largest[push] + diff;
+                smallest[push] = 1.max(1 - diff);
+                smallest[n] = smallest[push] + diff;
             }
-            OpCode::Mod => writeln!(out, "{} %= {};", a, b),
-            OpCode::Eql => writeln!(out, "{} = ({} == {}) as i64;", a, a, b),
-            OpCode::Inp => writeln!(out, "\n{} = input.next().unwrap() as i64;", a),
+            _ => return None,
+        }
+    }
+    if !stack.is_empty() {
+        return None;
+    }
+    Some((
+        ModelNumber::from_digits(&largest),
+        ModelNumber::from_digits(&smallest),
+    ))
+}
+
+/// Uses `solve()` if the program has the usual digit blocks, and searches otherwise.
+fn model_number(program: &Input, order: Order) -> usize {
+    let digits = match (solve(program), order) {
+        (Some((largest, _)), Order::MaxFirst) => return largest.value(),
+        (Some((_, smallest)), Order::MinFirst) => return smallest.value(),
+        (None, _) => search(program, &[1, 2, 3, 4, 5, 6, 7, 8, 9], order),
+    };
+    let digits = digits.expect("No valid model number");
+    digits.iter().fold(0, |acc, &d| acc * 10 + d as usize)
+}
+
+fn part1(program: &Input) -> usize {
+    model_number(program, Order::MaxFirst)
+}
+
+fn part2(program: &Input) -> usize {
+    model_number(program, Order::MinFirst)
+}
+
+#[test]
+fn compiled_alu() {
+    let program = load_input(crate::load_strings(crate::data_file!()));
+    let mut alu = Alu {
+        program: &program,
+        regs: [0; 4],
+    };
+    let mut model = ModelNumber::new();
+    for _ in 0..1000 {
+        for _ in 0..997 {
+            model.decrement();
         }
-        .unwrap();
+        let digits = || model.0.iter().map(|d| d.0.get());
+        assert!(alu.run_with_input(digits()));
+        assert_eq!(
+            super::alu::alu(digits()),
+            alu.regs[Reg::Z as usize],
+            "{}",
+            model
+        );
     }
+}
 
-    writeln!(out, "z }}").unwrap();
+#[test]
+fn control_flow() {
+    use crate::cfg::{Cfg, Target};
+
+    let program = load_input(crate::load_strings(crate::data_file!()));
+    let cfg = Cfg::build(&program);
+    assert_eq!(cfg.blocks.len(), 14);
+    assert!(cfg.blocks.iter().all(|b| b.end - b.start == 18));
+    assert_eq!(cfg.blocks[13].succs, [Target::Exit]);
+    assert!(cfg.loops().is_empty());
+    let dot = cfg.to_dot(&program, &[4, 22]);
+    assert!(dot.contains("b0 -> b1;\n"));
+    assert!(dot.contains("\\l* 4: div z 1\\l"));
+    assert_eq!(dot.matches("fillcolor").count(), 2);
 }
 
 #[test]
 fn real_data() {
+    use super::alu_opt::{optimize, Interval};
+
     let d = load_input(crate::load_strings(crate::data_file!()));
-    assert_eq!(part1(&d), 1);
-    // assert_eq!(part2(&d), 1);
+    let (largest, smallest) = solve(&d).unwrap();
+    for model in &[largest, smallest] {
+        assert_eq!(super::alu::alu(model.0.iter().map(|d| d.0.get())), 0);
+    }
+    assert_eq!(part1(&d), 51983999947999);
+    assert_eq!(part2(&d), 11211791111365);
+
+    assert!(solve(&d[18..]).is_none());
+    // The optimized program doesn't have the digit blocks, so it is searched instead.
+    let optimized = optimize(&d, &[Interval::digit(); 14], &[Reg::Z]);
+    assert!(solve(&optimized).is_none());
+    assert_eq!(part1(&optimized), 51983999947999);
+    assert_eq!(part2(&optimized), 11211791111365);
+    let mut unbalanced = d.clone();
+    unbalanced[3 * 18 + 4].b = Either::Right(1);
+    assert!(solve(&unbalanced).is_none());
 }
 
 // #[test]
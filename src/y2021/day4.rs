@@ -79,10 +79,8 @@ impl Board {
     }
 
     fn check_win(&mut self, coord: &Coord) {
-        let hc = Coord { x: -1, y: coord.y };
-        let vc = Coord { x: coord.x, y: -1 };
-        self.won = self.grid.line(hc, (1, 0)).all(|t| t.marked)
-            || self.grid.line(vc, (0, 1)).all(|t| t.marked);
+        self.won = self.grid.row(coord.y).iter().all(|t| t.marked)
+            || self.grid.column(coord.x).all(|t| t.marked);
     }
 
     fn final_score(&self, n: usize) -> usize {
@@ -4,3 +4,5 @@ crate::advent!(
 );
 
 mod alu;
+mod alu_opt;
+mod alu_search;